@@ -1,6 +1,8 @@
 use std::hint::black_box;
 
-use criterion::{Criterion, criterion_group, criterion_main};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use gio::prelude::*;
+use gst_thumbnailers::{ScalePreset, ThumbnailRequest, ThumbnailerKind};
 
 pub const TEST_VIDEOS: &[(&str, &str)] = &[
     (
@@ -15,15 +17,7 @@ pub const TEST_VIDEOS: &[(&str, &str)] = &[
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     for (video_url, video_file) in TEST_VIDEOS {
-        let path = format!("benches/{video_file}");
-        if std::fs::File::open(&path).is_err() {
-            eprintln!("Downloading video data from <{video_url}>.\nThis might take a while …");
-
-            std::process::Command::new("curl")
-                .args([video_url, "--output", path.as_str()])
-                .status()
-                .unwrap();
-        }
+        let path = download_video(video_url, video_file);
 
         c.bench_function(video_file, |b| b.iter(|| run_thumbnailer(black_box(&path))));
     }
@@ -38,7 +32,272 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     )
 }
 
-criterion_group!(benches, criterion_benchmark);
+fn download_video(video_url: &str, video_file: &str) -> String {
+    let path = format!("benches/{video_file}");
+    if std::fs::File::open(&path).is_err() {
+        eprintln!("Downloading video data from <{video_url}>.\nThis might take a while …");
+
+        std::process::Command::new("curl")
+            .args([video_url, "--output", path.as_str()])
+            .status()
+            .unwrap();
+    }
+
+    path
+}
+
+pub const THUMBNAIL_SIZES: &[u16] = &[64, 128, 256, 512, 1024];
+
+pub fn size_benchmark(c: &mut Criterion) {
+    // The 720p video is large enough to not limit the thumbnail size
+    let (video_url, video_file) = TEST_VIDEOS[1];
+    let path = download_video(video_url, video_file);
+
+    let mut group = c.benchmark_group("size");
+    for size in THUMBNAIL_SIZES {
+        // Pixels of the bounding box
+        group.throughput(Throughput::Elements(*size as u64 * *size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, size| {
+            let size = size.to_string();
+            b.iter(|| {
+                gst_thumbnailers::main_video_thumbnailer([
+                    "gst-video-thumbnailer",
+                    "-p",
+                    &path,
+                    "-o",
+                    "/dev/null",
+                    "-s",
+                    black_box(&size),
+                ])
+                .unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+pub const BATCH_VIDEOS: &[&str] = &[
+    "tests/1.webm",
+    "tests/2.webm",
+    "tests/3.webm",
+    "tests/uneven.webm",
+];
+
+pub fn batch_benchmark(c: &mut Criterion) {
+    let requests = BATCH_VIDEOS
+        .iter()
+        .zip(0..)
+        .map(|(path, id)| ThumbnailRequest {
+            id,
+            source_uri: gio::File::for_path(path).uri().to_string(),
+            output_path: "/dev/null".into(),
+            kind: ThumbnailerKind::Video,
+            config_override: None,
+        })
+        .collect::<Vec<_>>();
+    let config = Default::default();
+
+    let mut group = c.benchmark_group("batch");
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            for path in BATCH_VIDEOS {
+                run_thumbnailer(black_box(path));
+            }
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| {
+            for result in gst_thumbnailers::thumbnail_batch(black_box(&requests), &config) {
+                result.outcome.unwrap();
+            }
+        })
+    });
+    group.bench_function("parallel", |b| {
+        let parallelism = gst_thumbnailers::default_batch_parallelism();
+        b.iter(|| {
+            for result in gst_thumbnailers::thumbnail_batch_parallel(
+                black_box(&requests),
+                &config,
+                parallelism,
+            ) {
+                result.outcome.unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+pub const TEST_AUDIO: &[&str] = &["tests/audio-cover-jpg.mp3", "tests/audio-cover-png.flac"];
+
+pub fn audio_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("audio");
+    for path in TEST_AUDIO {
+        group.bench_function(*path, |b| {
+            b.iter(|| {
+                gst_thumbnailers::main_audio_thumbnailer([
+                    "gst-audio-thumbnailer",
+                    "-p",
+                    black_box(path),
+                    "-o",
+                    "/dev/null",
+                    "-s",
+                    "256",
+                ])
+                .unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+pub fn png_compression_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("png_compression");
+    for compression in ["fast", "default", "best"] {
+        group.bench_function(compression, |b| {
+            b.iter(|| {
+                gst_thumbnailers::main_video_thumbnailer([
+                    "gst-video-thumbnailer",
+                    "-p",
+                    "tests/1.webm",
+                    "-o",
+                    "/dev/null",
+                    "-s",
+                    "256",
+                    "--png-compression",
+                    black_box(compression),
+                ])
+                .unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+pub fn rotation_benchmark(c: &mut Criterion) {
+    let uri = gio::File::for_path("tests/1.webm").uri();
+
+    let mut group = c.benchmark_group("rotation");
+    for apply_rotation in [true, false] {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            apply_rotation,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(apply_rotation),
+            &config,
+            |b, config| {
+                b.iter(|| {
+                    gst_thumbnailers::thumbnail_video_to_bytes(black_box(&uri), config, None)
+                        .unwrap();
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+pub const INTERMEDIATE_SCALE_FACTORS: &[f32] = &[1., 2., 4.];
+
+pub fn intermediate_scale_benchmark(c: &mut Criterion) {
+    // Scaling is only done for cover art
+    let uri = gio::File::for_path("tests/audio-cover-jpg.mp3").uri();
+
+    let mut group = c.benchmark_group("intermediate_scale");
+    for factor in INTERMEDIATE_SCALE_FACTORS {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            intermediate_scale_factor: *factor,
+            ..Default::default()
+        };
+
+        // Blurrier thumbnails have a lower variance
+        let thumbnail = gst_thumbnailers::thumbnail_audio_to_bytes(&uri, &config, None).unwrap();
+        eprintln!(
+            "Variance with intermediate scale factor {factor}: {}",
+            thumbnail_variance(thumbnail)
+        );
+
+        group.bench_with_input(BenchmarkId::from_parameter(factor), &config, |b, config| {
+            b.iter(|| {
+                gst_thumbnailers::thumbnail_audio_to_bytes(black_box(&uri), config, None).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn thumbnail_variance(data: Vec<u8>) -> f32 {
+    let loader = gly::Loader::for_bytes(&gly::glib::Bytes::from_owned(data));
+    let frame = loader.load().unwrap().next_frame().unwrap();
+    gst_thumbnailers::variance(&gst_thumbnailers::FrameRef {
+        data: &frame.buf_bytes(),
+        width: frame.width(),
+        height: frame.height(),
+        stride: frame.stride(),
+    })
+}
+
+pub fn scale_preset_benchmark(c: &mut Criterion) {
+    gst::init().unwrap();
+
+    // 4K cover art as uncompressed PPM, so that decoding it takes little time
+    let (width, height) = (3840, 2160);
+    let mut ppm = format!("P6 {width} {height} 255\n").into_bytes();
+    ppm.extend((0..width * height * 3).map(|i| (i % 251) as u8));
+    let source = gst_thumbnailers::ThumbnailSource::CoverArt(
+        gst::Sample::builder()
+            .buffer(&gst::Buffer::from_slice(ppm))
+            .build(),
+    );
+
+    let mut group = c.benchmark_group("scale_preset");
+    for preset in [
+        ScalePreset::Fast,
+        ScalePreset::Balanced,
+        ScalePreset::HighQuality,
+    ] {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            scale_filters: preset.into(),
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{preset:?}")),
+            &config,
+            |b, config| b.iter(|| black_box(&source).encode_to_bytes(config).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+pub fn variance_benchmark(c: &mut Criterion) {
+    // 4K RGB frame
+    let (width, height) = (3840, 2160);
+    let xs = (0..width * 3 * height)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("variance");
+    group.bench_function("scalar", |b| {
+        b.iter(|| gst_thumbnailers::variance_scalar(black_box(&xs), width, width * 3, height))
+    });
+    #[cfg(feature = "simd")]
+    group.bench_function("simd", |b| {
+        b.iter(|| gst_thumbnailers::variance_simd(black_box(&xs), width, width * 3, height))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    size_benchmark,
+    batch_benchmark,
+    audio_benchmark,
+    png_compression_benchmark,
+    rotation_benchmark,
+    intermediate_scale_benchmark,
+    scale_preset_benchmark,
+    variance_benchmark
+);
 criterion_main!(benches);
 
 fn run_thumbnailer(video: &str) {
@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+use rayon::prelude::*;
+
+use crate::{Error, Result, ThumbnailerConfig};
+
+/// Upper limit for [`default_batch_parallelism`], since every pipeline keeps
+/// decoded frames in memory
+const MAX_DEFAULT_PARALLELISM: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailerKind {
+    Video,
+    Audio,
+}
+
+pub struct ThumbnailRequest {
+    /// Chosen by the caller to match the result to the request
+    pub id: u64,
+    /// URI of the file to create the thumbnail for
+    pub source_uri: String,
+    /// Path under which to output the thumbnail as PNG
+    pub output_path: PathBuf,
+    pub kind: ThumbnailerKind,
+    /// Used instead of the configuration of the batch or queue
+    ///
+    /// The verbosity, which is set when initializing, is still taken from
+    /// the batch or queue.
+    pub config_override: Option<ThumbnailerConfig>,
+}
+
+#[derive(Debug)]
+pub struct ThumbnailResult {
+    /// [`ThumbnailRequest::id`] of the request
+    pub id: u64,
+    pub outcome: Result<ThumbnailMeta>,
+}
+
+/// Information about a thumbnail that was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbnailMeta {
+    pub width: u32,
+    pub height: u32,
+    /// Position of the video frame in the video
+    ///
+    /// `None` for cover art and if the position isn't known.
+    pub source_frame_timestamp: Option<gst::ClockTime>,
+}
+
+/// Create thumbnails for multiple files
+///
+/// GStreamer is only initialized once, but every request gets its own
+/// pipeline. The results are in the same order as the requests. A failing
+/// request doesn't abort the remaining ones. [`crate::DecoderThreads::Auto`]
+/// uses a single decoder thread per pipeline.
+pub fn thumbnail_batch(
+    requests: &[ThumbnailRequest],
+    config: &ThumbnailerConfig,
+) -> Vec<ThumbnailResult> {
+    if let Err(err) = crate::init(config) {
+        return requests
+            .iter()
+            .map(|request| ThumbnailResult::error(request, &err))
+            .collect();
+    }
+
+    requests
+        .iter()
+        .map(|request| thumbnail_request(request, config))
+        .collect()
+}
+
+/// Like [`thumbnail_batch`] but processes up to `parallelism` requests at
+/// the same time
+///
+/// Every request is still processed by its own pipeline. Each of them uses
+/// [`ThumbnailerConfig::decoder_threads`] decoder threads, a single one
+/// with [`crate::DecoderThreads::Auto`].
+pub fn thumbnail_batch_parallel(
+    requests: &[ThumbnailRequest],
+    config: &ThumbnailerConfig,
+    parallelism: usize,
+) -> Vec<ThumbnailResult> {
+    if let Err(err) = crate::init(config) {
+        return requests
+            .iter()
+            .map(|request| ThumbnailResult::error(request, &err))
+            .collect();
+    }
+
+    let pool = match rayon::ThreadPoolBuilder::new()
+        .num_threads(parallelism.max(1))
+        .build()
+    {
+        Ok(pool) => pool,
+        Err(err) => {
+            let err = Error::other(err);
+            return requests
+                .iter()
+                .map(|request| ThumbnailResult::error(request, &err))
+                .collect();
+        }
+    };
+
+    pool.install(|| {
+        requests
+            .par_iter()
+            .map(|request| thumbnail_request(request, config))
+            .collect()
+    })
+}
+
+/// Number of available CPUs, but at most 4 to limit memory usage
+pub fn default_batch_parallelism() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get().min(MAX_DEFAULT_PARALLELISM))
+}
+
+/// Receives the result of every request pushed to a [`ThumbnailQueue`]
+pub trait ResultSink: Fn(ThumbnailResult) + Send + Sync {}
+
+impl<F: Fn(ThumbnailResult) + Send + Sync> ResultSink for F {}
+
+/// Requests that are processed by worker threads as they arrive
+///
+/// For long-running services. The queue is bounded, so that callers notice
+/// when requests arrive faster than they can be processed. Like in
+/// [`thumbnail_batch_parallel`], [`crate::DecoderThreads::Auto`] uses a
+/// single decoder thread per pipeline.
+#[derive(Debug, Clone)]
+pub struct ThumbnailQueue {
+    sender: SyncSender<ThumbnailRequest>,
+}
+
+impl ThumbnailQueue {
+    /// Start `workers` threads processing the requests
+    ///
+    /// Up to `queue_depth` requests wait for a worker. The returned thread
+    /// finishes once all clones of the queue are dropped and the remaining
+    /// requests are processed.
+    pub fn new(
+        config: ThumbnailerConfig,
+        workers: usize,
+        queue_depth: usize,
+        sink: impl ResultSink + 'static,
+    ) -> (Self, JoinHandle<()>) {
+        let (sender, receiver) = mpsc::sync_channel::<ThumbnailRequest>(queue_depth);
+
+        let handle = std::thread::spawn(move || {
+            if let Err(err) = crate::init(&config) {
+                for request in receiver {
+                    sink(ThumbnailResult::error(&request, &err));
+                }
+                return;
+            }
+
+            let receiver = Mutex::new(receiver);
+            std::thread::scope(|scope| {
+                for _ in 0..workers.max(1) {
+                    scope.spawn(|| {
+                        loop {
+                            // Don't keep the receiver locked while processing
+                            let request = receiver.lock().unwrap().recv();
+                            let Ok(request) = request else {
+                                break;
+                            };
+                            sink(thumbnail_request(&request, &config));
+                        }
+                    });
+                }
+            });
+        });
+
+        (Self { sender }, handle)
+    }
+
+    /// Add a request without blocking
+    ///
+    /// Fails if `queue_depth` requests are already waiting.
+    pub fn push(&self, request: ThumbnailRequest) -> Result<()> {
+        self.sender.try_send(request).map_err(|err| match err {
+            TrySendError::Full(_) => Error::other("Thumbnail queue is full"),
+            TrySendError::Disconnected(_) => Error::other("Thumbnail queue stopped"),
+        })
+    }
+}
+
+fn thumbnail_request(request: &ThumbnailRequest, config: &ThumbnailerConfig) -> ThumbnailResult {
+    let config = request.config_override.as_ref().unwrap_or(config);
+    let outcome = match request.kind {
+        ThumbnailerKind::Video => crate::create_video_thumbnail(
+            &request.source_uri,
+            &request.output_path,
+            config,
+            config.decoder_threads.threads_in_batch(),
+            None,
+        ),
+        ThumbnailerKind::Audio => {
+            crate::create_audio_thumbnail(&request.source_uri, &request.output_path, config, None)
+        }
+    };
+
+    ThumbnailResult {
+        id: request.id,
+        outcome,
+    }
+}
+
+impl ThumbnailResult {
+    /// Failure that affects all requests, like failing to initialize
+    fn error(request: &ThumbnailRequest, err: &Error) -> Self {
+        Self {
+            id: request.id,
+            outcome: Err(err.clone()),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use gio::glib;
+use gio::prelude::*;
+
+/// Size directories of the freedesktop.org thumbnail cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum ThumbnailFlavor {
+    /// Up to 128×128 pixels
+    Normal,
+    /// Up to 256×256 pixels
+    Large,
+    /// Up to 512×512 pixels
+    #[value(name = "x-large")]
+    #[cfg_attr(feature = "serde", serde(rename = "x-large"))]
+    XLarge,
+    /// Up to 1024×1024 pixels
+    #[value(name = "xx-large")]
+    #[cfg_attr(feature = "serde", serde(rename = "xx-large"))]
+    XXLarge,
+}
+
+impl ThumbnailFlavor {
+    pub const ALL: [Self; 4] = [Self::Normal, Self::Large, Self::XLarge, Self::XXLarge];
+
+    /// Name of the directory, also used in the D-Bus interface
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Large => "large",
+            Self::XLarge => "x-large",
+            Self::XXLarge => "xx-large",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|flavor| flavor.name() == name)
+    }
+
+    /// Maximum width and height of the thumbnails
+    pub fn size(self) -> u16 {
+        match self {
+            Self::Normal => 128,
+            Self::Large => 256,
+            Self::XLarge => 512,
+            Self::XXLarge => 1024,
+        }
+    }
+}
+
+/// Path of the thumbnail for `uri` in the thumbnail cache of the user
+///
+/// This is `$XDG_CACHE_HOME/thumbnails/{flavor}/{md5 of uri}.png`, as
+/// specified by freedesktop.org. The directory might not exist yet.
+pub fn xdg_thumbnail_path(uri: &str, flavor: ThumbnailFlavor) -> PathBuf {
+    thumbnail_path(&glib::user_cache_dir().join("thumbnails"), uri, flavor)
+}
+
+/// Path of the thumbnail for `uri` in a thumbnail cache at `thumbnails_dir`
+pub(crate) fn thumbnail_path(thumbnails_dir: &Path, uri: &str, flavor: ThumbnailFlavor) -> PathBuf {
+    let hash = glib::compute_checksum_for_string(glib::ChecksumType::Md5, uri).unwrap();
+    thumbnails_dir
+        .join(flavor.name())
+        .join(format!("{hash}.png"))
+}
+
+/// Whether the PNG thumbnail at `thumb_path` shows the current version of
+/// the file at `source_uri`
+///
+/// Compares the `Thumb::URI` and `Thumb::MTime` text chunks with the URI and
+/// the modification time in seconds since the Unix epoch. Missing, corrupt,
+/// and outdated thumbnails are not valid.
+pub fn is_thumbnail_valid(thumb_path: &Path, source_uri: &str, source_mtime: i64) -> bool {
+    let Ok(file) = File::open(thumb_path) else {
+        return false;
+    };
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_ignore_text_chunk(false);
+    let Ok(reader) = decoder.read_info() else {
+        return false;
+    };
+
+    let info = reader.info();
+    let text = |key: &str| {
+        info.uncompressed_latin1_text
+            .iter()
+            .find(|chunk| chunk.keyword == key)
+            .map(|chunk| chunk.text.clone())
+            .or_else(|| {
+                info.utf8_text
+                    .iter()
+                    .find(|chunk| chunk.keyword == key)
+                    .and_then(|chunk| chunk.get_text().ok())
+            })
+    };
+
+    text("Thumb::URI").as_deref() == Some(source_uri)
+        && text("Thumb::MTime") == Some(source_mtime.to_string())
+}
+
+/// Modification time of the file at `uri` in seconds since the Unix epoch
+pub(crate) fn source_mtime(uri: &str) -> Option<i64> {
+    gio::File::for_uri(uri)
+        .query_info(
+            gio::FILE_ATTRIBUTE_TIME_MODIFIED,
+            gio::FileQueryInfoFlags::NONE,
+            gio::Cancellable::NONE,
+        )
+        .ok()?
+        .modification_date_time()
+        .map(|time| time.to_unix())
+}
@@ -1,18 +1,273 @@
-use std::path::PathBuf;
+use std::convert::Infallible;
+use std::num::NonZeroU8;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use gio::prelude::*;
 
+use crate::Error;
+use crate::cache::{ThumbnailFlavor, is_thumbnail_valid, source_mtime, xdg_thumbnail_path};
+use crate::config::{
+    AudioFallback, DecoderThreads, FrameQualityMetric, OutputFormat, PngCompression, ScalePreset,
+    SeekPositions, SizeMode, ThumbnailerConfig, Verbosity,
+};
+
 #[derive(Debug, clap::Parser)]
 #[command(version, about)]
 pub struct Args {
     #[clap(flatten)]
     pub source: Source,
-    #[clap(short, long)]
-    /// Path under which to output the thumbnail as PNG
-    pub output: PathBuf,
-    #[clap(short, long)]
+    #[clap(short, long, required_unless_present = "cache")]
+    /// Path under which to output the thumbnail
+    ///
+    /// Can contain placeholders: %b for the input file name without
+    /// extension, %d for its directory, %e for its extension, %W and %H for
+    /// the thumbnail width and height, and %% for a literal %. Any other %
+    /// is kept as is.
+    pub output: Option<OutputPath>,
+    #[clap(long, value_enum, value_name = "FLAVOR", conflicts_with = "output")]
+    /// Output the thumbnail to the thumbnail cache of the user
+    ///
+    /// The size of the flavor is used if no size is given.
+    pub cache: Option<ThumbnailFlavor>,
+    #[clap(long)]
+    /// Don't create the thumbnail if the output path already has one for the
+    /// current version of the input file
+    pub skip_if_cached: bool,
+    #[clap(short, long, value_enum)]
+    /// Image format of the thumbnail, guessed from the output path by default
+    pub format: Option<OutputFormat>,
+    #[clap(
+        long,
+        value_name = "QUALITY",
+        default_value = "85",
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    /// Quality between 1 and 100 used for JPEG output
+    pub jpeg_quality: u8,
+    #[clap(long, value_enum, default_value = "default")]
+    /// Trade-off between encoding speed and size for PNG output
+    pub png_compression: PngCompression,
+    #[clap(long, value_enum, default_value = "balanced")]
+    /// Trade-off between speed and quality when scaling down cover art
+    pub scale_preset: ScalePreset,
+    #[clap(
+        short,
+        long,
+        required_unless_present_all = ["width", "height"],
+        required_unless_present = "cache"
+    )]
     /// Maximum size for width and height of the thumbnail
-    pub size: u16,
+    pub size: Option<u16>,
+    #[clap(long, value_enum, default_value = "fit")]
+    /// How the thumbnail size is determined
+    pub size_mode: SizeModeName,
+    #[clap(long)]
+    /// Width of the thumbnail in exact size mode, defaults to size
+    pub width: Option<u16>,
+    #[clap(long)]
+    /// Height of the thumbnail in exact size mode, defaults to size
+    pub height: Option<u16>,
+    #[clap(long, value_enum, default_value = "rgb")]
+    /// Metric used to select the most informative video frame
+    pub frame_metric: FrameQualityMetric,
+    #[clap(long, default_value = "5")]
+    /// Number of video frames to choose the thumbnail from
+    pub num_frames: NonZeroU8,
+    #[clap(long)]
+    /// Use hardware video decoders if available
+    ///
+    /// They are faster, but can be less reliable and use more memory than
+    /// software decoders.
+    pub allow_hw_decoders: bool,
+    #[clap(long = "allow-hw-decoder", value_name = "FACTORY", num_args = 0..)]
+    /// Hardware video decoder to use even without --allow-hw-decoders
+    pub allow_hw_decoder: Option<Vec<String>>,
+    #[clap(long = "disable-decoder", value_name = "FACTORY")]
+    /// Never use this video decoder
+    ///
+    /// Can be given multiple times.
+    pub disable_decoders: Vec<String>,
+    #[clap(long, value_name = "auto|N", default_value = "auto")]
+    /// Number of worker threads used by video decoders
+    ///
+    /// With auto, one per CPU core but at most four.
+    pub decoder_threads: DecoderThreads,
+    #[clap(long, value_name = "SECONDS")]
+    /// Abort if the pipeline doesn't make progress for the given time
+    pub timeout: Option<u64>,
+    #[clap(long)]
+    /// Create the directory of the output file if it doesn't exist
+    pub create_output_dir: bool,
+    #[clap(long = "cover-art-fallback-dir", value_name = "DIR")]
+    /// Directory, relative to the audio file, to search for files like
+    /// folder.jpg if there is no embedded cover art
+    ///
+    /// Can be given multiple times.
+    pub cover_art_fallback_dirs: Vec<PathBuf>,
+    #[clap(long, value_name = "N")]
+    /// Take the video thumbnail at the start of chapter N, counting from 0
+    pub chapter: Option<usize>,
+    #[clap(long)]
+    /// Tone-map HDR video, requires the tonemap GStreamer element
+    pub tone_map_hdr: bool,
+    #[clap(long, value_enum, value_name = "DEGREES")]
+    /// Rotate video clockwise, ignoring the orientation stored in the file
+    pub rotation: Option<Rotation>,
+    #[clap(long, value_enum, default_value = "error")]
+    /// What to output for audio files without cover art
+    pub audio_fallback: AudioFallback,
+    #[clap(long)]
+    /// Only check that a thumbnail can be created, without writing it
+    pub dry_run: bool,
+    #[clap(short, long)]
+    /// Explain which stream and frame are used for the thumbnail
+    pub verbose: bool,
+}
+
+impl Args {
+    /// The output path, or the path in the thumbnail cache with `--cache`
+    pub fn output(&self) -> crate::Result<OutputPath> {
+        match self.cache {
+            Some(flavor) => Ok(OutputPath::Literal(xdg_thumbnail_path(
+                &self.source.uri()?,
+                flavor,
+            ))),
+            None => Ok(self.output.clone().unwrap()),
+        }
+    }
+
+    /// Whether `--skip-if-cached` is given and the output is up to date
+    pub fn is_cached(&self) -> crate::Result<bool> {
+        if !self.skip_if_cached {
+            return Ok(false);
+        }
+        let OutputPath::Literal(path) = self.output()? else {
+            return Ok(false);
+        };
+
+        let uri = self.source.uri()?;
+        Ok(source_mtime(&uri).is_some_and(|mtime| is_thumbnail_valid(&path, &uri, mtime)))
+    }
+
+    pub fn config(&self) -> ThumbnailerConfig {
+        let size = self.size.or(self.cache.map(ThumbnailFlavor::size));
+        let size_mode = match self.size_mode {
+            SizeModeName::Fit => SizeMode::Fit {
+                max: size.or(self.width.max(self.height)).unwrap(),
+            },
+            SizeModeName::Exact => SizeMode::Exact {
+                width: self.width.or(size).unwrap(),
+                height: self.height.or(size).unwrap(),
+                background: [0, 0, 0],
+            },
+            SizeModeName::CropFill => SizeMode::CropFill {
+                size: size.or(self.width.min(self.height)).unwrap(),
+            },
+        };
+
+        ThumbnailerConfig {
+            size_mode,
+            frame_selector: self.frame_metric.selector(),
+            num_seek_positions: self.num_frames,
+            allow_hardware_decoders: self.allow_hw_decoders,
+            decoder_allowlist: self.allow_hw_decoder.clone(),
+            decoder_denylist: self.disable_decoders.clone(),
+            decoder_threads: self.decoder_threads,
+            pipeline_timeout: self.timeout.map(Duration::from_secs),
+            // The cache directories might not exist yet
+            create_output_dir: self.create_output_dir || self.cache.is_some(),
+            output_format: self
+                .format
+                .or_else(|| {
+                    // Paths in the cache always end with .png
+                    let output = self.output.as_ref()?;
+                    OutputFormat::from_extension(output.as_path())
+                })
+                .unwrap_or(OutputFormat::Png),
+            jpeg_quality: self.jpeg_quality,
+            png_compression: self.png_compression,
+            scale_filters: self.scale_preset.into(),
+            cover_art_fallback_dirs: self.cover_art_fallback_dirs.clone(),
+            seek_positions: self
+                .chapter
+                .map_or(SeekPositions::Percentages, SeekPositions::Chapter),
+            tone_map_hdr: self.tone_map_hdr,
+            forced_rotation: self.rotation.map(Rotation::orientation_method),
+            audio_fallback: self.audio_fallback,
+            dry_run: self.dry_run,
+            verbosity: if self.verbose {
+                Verbosity::Verbose
+            } else {
+                Verbosity::Normal
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum SizeModeName {
+    /// Keep the aspect ratio within a box of the given size
+    Fit,
+    /// Output exactly the given width and height, padding with black
+    Exact,
+    /// Fill a square of the given size, cropping the edges
+    CropFill,
+}
+
+#[derive(Debug, Clone)]
+pub enum OutputPath {
+    Literal(PathBuf),
+    /// Path with placeholders for [`crate::expand_template`]
+    Template(String),
+}
+
+impl OutputPath {
+    /// The path or the unexpanded template, for guessing the format
+    fn as_path(&self) -> &Path {
+        match self {
+            Self::Literal(path) => path,
+            Self::Template(template) => Path::new(template),
+        }
+    }
+}
+
+impl FromStr for OutputPath {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if crate::template::is_template(s) {
+            Ok(Self::Template(s.to_string()))
+        } else {
+            Ok(Self::Literal(s.into()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Rotation {
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Clockwise,
+    #[value(name = "180")]
+    UpsideDown,
+    #[value(name = "270")]
+    CounterClockwise,
+}
+
+impl Rotation {
+    fn orientation_method(self) -> gst_video::VideoOrientationMethod {
+        match self {
+            Self::None => gst_video::VideoOrientationMethod::Identity,
+            Self::Clockwise => gst_video::VideoOrientationMethod::_90r,
+            Self::UpsideDown => gst_video::VideoOrientationMethod::_180,
+            Self::CounterClockwise => gst_video::VideoOrientationMethod::_90l,
+        }
+    }
 }
 
 #[derive(Debug, clap::Args)]
@@ -21,17 +276,38 @@ pub struct Source {
     /// URI of file to create the thumbnail for
     #[clap(short, long)]
     pub input_uri: Option<String>,
-    /// Path of the file to create the thumbnail for
+    /// Path of the file to create the thumbnail for, `-` to read from stdin
     #[clap(short = 'p', long)]
     pub input_path: Option<PathBuf>,
 }
 
 impl Source {
-    pub fn uri(&self) -> String {
-        self.input_uri.clone().unwrap_or_else(|| {
-            gio::File::for_path(self.input_path.clone().unwrap())
-                .uri()
-                .to_string()
-        })
+    /// Fails for paths that GIO can't handle, like ones containing NUL
+    /// bytes
+    pub fn uri(&self) -> crate::Result<String> {
+        if self.is_stdin() {
+            return Ok(crate::STDIN_URI.to_string());
+        }
+        if let Some(uri) = &self.input_uri {
+            return Ok(uri.clone());
+        }
+
+        let path = self.input_path.as_deref().unwrap();
+        if path.as_os_str().as_bytes().contains(&0) {
+            return Err(Error::other(format!(
+                "Input path {path:?} contains a NUL byte"
+            )));
+        }
+
+        // Resolve `..` and symlinks, but keep paths that don't exist for
+        // the error message
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        Ok(gio::File::for_path(path).uri().to_string())
+    }
+
+    /// Whether the input is piped in via `--input-path -`
+    pub fn is_stdin(&self) -> bool {
+        self.input_path.as_deref() == Some(Path::new("-"))
     }
 }
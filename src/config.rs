@@ -0,0 +1,635 @@
+use std::fmt::Display;
+use std::num::{NonZeroU8, NonZeroU32};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::frame_selector::{
+    EdgeEnergyFrameSelector, FrameSelector, LuminanceVarianceFrameSelector,
+    RegionalVarianceFrameSelector, VarianceFrameSelector,
+};
+
+/// Options controlling how thumbnails are generated
+///
+/// With the `serde` feature, [`Self::frame_selector`],
+/// [`Self::on_progress`], and [`Self::forced_rotation`] are not serialized
+/// and keep their defaults when deserializing. Missing fields are set to
+/// their defaults as well.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ThumbnailerConfig {
+    /// Size of the generated thumbnail
+    pub size_mode: SizeMode,
+    /// Picks the most informative video frame
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub frame_selector: Box<dyn FrameSelector>,
+    /// Number of positions in the video at which candidate frames are taken
+    pub num_seek_positions: NonZeroU8,
+    /// Positions in percent of the video length at which candidate frames
+    /// are taken
+    ///
+    /// Overrides [`Self::num_seek_positions`] if set. Values have to be at
+    /// most 100.
+    pub seek_percentages: Option<Vec<u8>>,
+    /// Whether candidate frames are taken at percentages of the video length
+    /// or at the start of a chapter
+    pub seek_positions: SeekPositions,
+    /// Keep hardware video decoders available
+    ///
+    /// Hardware decoders are faster, but they are disabled by default since
+    /// GStreamer can't be told to prefer software decoders in uridecodebin3.
+    /// Depending on the driver they can fail on some streams, produce broken
+    /// frames, or allocate a lot of memory for a single thumbnail.
+    ///
+    /// Disabled decoders are removed from the process-wide GStreamer
+    /// registry while a pipeline prerolls. Pipelines with different decoder
+    /// settings therefore don't preroll at the same time.
+    pub allow_hardware_decoders: bool,
+    /// Hardware decoders to keep available even if
+    /// [`Self::allow_hardware_decoders`] is disabled
+    ///
+    /// Factory names like `nvh264dec`, for drivers that only work well for
+    /// some codecs. Only applies to pipelines using this configuration.
+    pub decoder_allowlist: Option<Vec<String>>,
+    /// Decoders that are never used
+    ///
+    /// Factory names like `avdec_h264`, for decoders that produce broken
+    /// frames when a better one is available. Unknown names are ignored.
+    pub decoder_denylist: Vec<String>,
+    /// Number of worker threads used by video decoders
+    pub decoder_threads: DecoderThreads,
+    /// Maximum time to wait for the pipeline to make progress
+    ///
+    /// Waits indefinitely if not set.
+    pub pipeline_timeout: Option<Duration>,
+    /// How often prerolling is retried if the pipeline fails to pause or
+    /// reports an error before it prerolled
+    ///
+    /// For transient failures like network glitches when reading from
+    /// `http://` URIs.
+    pub preroll_retries: u32,
+    /// Time to wait before retrying to preroll
+    pub preroll_retry_delay: Duration,
+    /// Called whenever video thumbnailing enters a new stage
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub on_progress: Option<Box<dyn Fn(ThumbnailerProgress) + Send + Sync>>,
+    /// Filters used for scaling down cover art
+    ///
+    /// Usually one of the [`ScalePreset`]s.
+    pub scale_filters: ScaleFilterPair,
+    /// Multiple of the thumbnail size that cover art is scaled to with the
+    /// coarse filter of [`Self::scale_filters`]
+    ///
+    /// Larger values give better quality but use more memory, smaller values
+    /// are faster. Has to be at least 1.
+    pub intermediate_scale_factor: f32,
+    /// Create missing parent directories of the output path
+    pub create_output_dir: bool,
+    /// Image format of the thumbnail
+    pub output_format: OutputFormat,
+    /// Quality between 1 and 100 used for JPEG output
+    pub jpeg_quality: u8,
+    /// Trade-off between encoding speed and size for PNG output
+    pub png_compression: PngCompression,
+    /// Which video stream to use for files with more than one
+    pub video_stream_selector: VideoStreamSelector,
+    /// Fail with [`crate::ErrorKind::BlankFrames`] if no candidate frame
+    /// has at least this variance
+    ///
+    /// Uses [`crate::variance`] independently of [`Self::frame_selector`].
+    /// Allows callers to handle blank videos differently.
+    pub min_variance_threshold: Option<f32>,
+    /// Frames with a mean luminance below this are only used if all frames
+    /// are that dark
+    ///
+    /// Skips black leader frames. Set to 0 to disable.
+    pub black_frame_threshold: u8,
+    /// Directories searched for files like `folder.jpg` if an audio file has
+    /// no embedded cover art
+    ///
+    /// Relative paths are resolved against the directory of the audio file,
+    /// so `"."` checks next to it. Only used for local files.
+    pub cover_art_fallback_dirs: Vec<PathBuf>,
+    /// Embedded images smaller than this width and height are skipped
+    ///
+    /// If all images are smaller, the largest one is used.
+    pub min_cover_resolution: Option<(u32, u32)>,
+    /// Tone-map HDR video to SDR with a `tonemap` element
+    ///
+    /// The element isn't part of the core GStreamer plugins, so
+    /// thumbnailing fails with [`crate::ErrorKind::MissingPlugin`] if it's
+    /// not installed. SDR video passes through unchanged.
+    pub tone_map_hdr: bool,
+    /// Rotate or flip video frames this way instead of following the
+    /// orientation tag
+    ///
+    /// For files that are rotated without being tagged as such.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub forced_rotation: Option<gst_video::VideoOrientationMethod>,
+    /// Rotate video frames according to their orientation
+    ///
+    /// If disabled, no `videoflip` element is added to the pipeline and
+    /// [`Self::forced_rotation`] is ignored.
+    pub apply_rotation: bool,
+    /// What to do for audio files without cover art
+    pub audio_fallback: AudioFallback,
+    /// Range of levels shown in [`AudioFallback::Spectrogram`], in dB below
+    /// the loudest level
+    pub spectrogram_dynamic_range_db: f32,
+    /// Create the thumbnail without writing it to the output path
+    ///
+    /// Checks whether a file can be thumbnailed.
+    pub dry_run: bool,
+    /// Which messages are printed to stderr
+    ///
+    /// With the `tracing` feature, all messages are passed to `tracing`
+    /// instead.
+    pub verbosity: Verbosity,
+}
+
+impl Default for ThumbnailerConfig {
+    fn default() -> Self {
+        Self {
+            size_mode: SizeMode::Fit { max: 256 },
+            frame_selector: Box::new(VarianceFrameSelector),
+            num_seek_positions: NonZeroU8::new(5).unwrap(),
+            seek_percentages: None,
+            seek_positions: SeekPositions::Percentages,
+            allow_hardware_decoders: false,
+            decoder_allowlist: None,
+            decoder_denylist: Vec::new(),
+            decoder_threads: DecoderThreads::Auto,
+            pipeline_timeout: None,
+            preroll_retries: 0,
+            preroll_retry_delay: Duration::from_millis(500),
+            on_progress: None,
+            scale_filters: ScaleFilterPair::default(),
+            intermediate_scale_factor: 2.,
+            create_output_dir: false,
+            output_format: OutputFormat::Png,
+            jpeg_quality: 85,
+            png_compression: PngCompression::Default,
+            video_stream_selector: VideoStreamSelector::First,
+            min_variance_threshold: None,
+            black_frame_threshold: 10,
+            cover_art_fallback_dirs: Vec::new(),
+            min_cover_resolution: None,
+            tone_map_hdr: false,
+            forced_rotation: None,
+            apply_rotation: true,
+            audio_fallback: AudioFallback::Error,
+            spectrogram_dynamic_range_db: 80.,
+            dry_run: false,
+            verbosity: Verbosity::Normal,
+        }
+    }
+}
+
+impl ThumbnailerConfig {
+    /// Configuration from `GST_THUMBNAILER_*` environment variables
+    ///
+    /// Supports `GST_THUMBNAILER_SIZE`, `GST_THUMBNAILER_FORMAT`,
+    /// `GST_THUMBNAILER_JPEG_QUALITY`, `GST_THUMBNAILER_NUM_FRAMES`,
+    /// `GST_THUMBNAILER_DECODER_THREADS`, `GST_THUMBNAILER_TIMEOUT_SECS`, and
+    /// `GST_THUMBNAILER_ALLOW_HW_DECODERS`. They take the same values as the
+    /// respective command line options. `GST_THUMBNAILER_SEEK_PERCENTAGES`
+    /// is a comma-separated list like `10,50,90` for
+    /// [`Self::seek_percentages`]. Absent variables keep their default.
+    pub fn from_env() -> crate::Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(max) = env_var("GST_THUMBNAILER_SIZE", parse)? {
+            config.size_mode = SizeMode::Fit { max };
+        }
+        if let Some(format) = env_var("GST_THUMBNAILER_FORMAT", |value| {
+            <OutputFormat as clap::ValueEnum>::from_str(value, true)
+        })? {
+            config.output_format = format;
+        }
+        if let Some(quality) = env_var("GST_THUMBNAILER_JPEG_QUALITY", parse)? {
+            config.jpeg_quality = quality;
+        }
+        if let Some(num_frames) = env_var("GST_THUMBNAILER_NUM_FRAMES", parse)? {
+            config.num_seek_positions = num_frames;
+        }
+        if let Some(percentages) = env_var("GST_THUMBNAILER_SEEK_PERCENTAGES", parse_percentages)? {
+            config.seek_percentages = Some(percentages);
+        }
+        if let Some(threads) = env_var("GST_THUMBNAILER_DECODER_THREADS", parse)? {
+            config.decoder_threads = threads;
+        }
+        if let Some(secs) = env_var("GST_THUMBNAILER_TIMEOUT_SECS", parse)? {
+            config.pipeline_timeout = Some(Duration::from_secs(secs));
+        }
+        if let Some(allow) = env_var("GST_THUMBNAILER_ALLOW_HW_DECODERS", parse)? {
+            config.allow_hardware_decoders = allow;
+        }
+
+        Ok(config)
+    }
+
+    /// Load the configuration from a TOML file
+    #[cfg(feature = "serde")]
+    pub fn from_toml_file(path: &Path) -> crate::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        toml::from_str(&data).map_err(crate::Error::other)
+    }
+
+    /// Store the configuration in a TOML file
+    #[cfg(feature = "serde")]
+    pub fn to_toml_file(&self, path: &Path) -> crate::Result<()> {
+        let data = toml::to_string(self).map_err(crate::Error::other)?;
+        std::fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn report_progress(&self, stage: ThumbnailerStage, fraction: f64) {
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(ThumbnailerProgress { stage, fraction });
+        }
+    }
+}
+
+fn env_var<T>(
+    name: &str,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> crate::Result<Option<T>> {
+    let value = match std::env::var(name) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(err) => return Err(crate::Error::other(format!("{name}: {err}"))),
+    };
+
+    parse(&value)
+        .map(Some)
+        .map_err(|err| crate::Error::other(format!("Invalid value {value:?} for {name}: {err}")))
+}
+
+fn parse<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.parse().map_err(|err: T::Err| err.to_string())
+}
+
+/// Comma-separated percentages between 0 and 100
+fn parse_percentages(value: &str) -> Result<Vec<u8>, String> {
+    value
+        .split(',')
+        .map(|percentage| {
+            let percentage = parse::<u8>(percentage.trim())?;
+            if percentage > 100 {
+                return Err(format!("{percentage} is more than 100%"));
+            }
+            Ok(percentage)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum DecoderThreads {
+    /// One thread per available CPU core, but at most four
+    ///
+    /// When running many thumbnailers in parallel, a single thread per
+    /// decoder is more efficient. Batches and queues therefore use a single
+    /// thread for this.
+    Auto,
+    Fixed(NonZeroU32),
+}
+
+impl DecoderThreads {
+    /// Upper limit for [`Self::Auto`], more threads barely speed up
+    /// decoding a few frames
+    const MAX_AUTO_THREADS: usize = 4;
+
+    /// Number of threads to configure decoders with
+    pub fn threads(self) -> NonZeroU32 {
+        match self {
+            Self::Auto => std::thread::available_parallelism()
+                .ok()
+                .and_then(|n| NonZeroU32::new(n.get().min(Self::MAX_AUTO_THREADS) as u32))
+                .unwrap_or(NonZeroU32::MIN),
+            Self::Fixed(threads) => threads,
+        }
+    }
+
+    /// Like [`Self::threads`], but a single thread for [`Self::Auto`]
+    pub(crate) fn threads_in_batch(self) -> NonZeroU32 {
+        match self {
+            Self::Auto => NonZeroU32::MIN,
+            Self::Fixed(threads) => threads,
+        }
+    }
+}
+
+impl FromStr for DecoderThreads {
+    type Err = String;
+
+    /// `auto` or a positive number
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            Ok(Self::Auto)
+        } else {
+            s.parse()
+                .map(Self::Fixed)
+                .map_err(|err| format!("expected \"auto\" or a positive number: {err}"))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum SizeMode {
+    /// Scale to fit into a `max` × `max` box
+    Fit { max: u16 },
+    /// Scale to fit into `width` × `height` and fill the rest with
+    /// `background`
+    Exact {
+        width: u16,
+        height: u16,
+        background: [u8; 3],
+    },
+    /// Scale until the shorter side is `size` and crop the center to a
+    /// `size` × `size` square
+    CropFill { size: u16 },
+}
+
+impl SizeMode {
+    /// Dimensions to which content of the given size is scaled before it
+    /// is padded or cropped
+    pub fn scaled_dimensions(&self, width: f32, height: f32) -> (u32, u32) {
+        match *self {
+            Self::Fit { max } => crate::geometry::fit_dimensions(width, height, max, max),
+            Self::Exact {
+                width: max_width,
+                height: max_height,
+                ..
+            } => crate::geometry::fit_dimensions(width, height, max_width, max_height),
+            Self::CropFill { size } => crate::geometry::fill_dimensions(width, height, size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FrameQualityMetric {
+    /// Variance over all RGB components
+    Rgb,
+    /// Variance of the BT.709 luminance
+    Luminance,
+    /// Sharpness measured via Sobel edge detection
+    EdgeEnergy,
+    /// Highest RGB variance of any cell in a 3×3 grid
+    Regional,
+}
+
+impl FrameQualityMetric {
+    pub fn selector(self) -> Box<dyn FrameSelector> {
+        match self {
+            Self::Rgb => Box::new(VarianceFrameSelector),
+            Self::Luminance => Box::new(LuminanceVarianceFrameSelector),
+            Self::EdgeEnergy => Box::new(EdgeEnergyFrameSelector),
+            Self::Regional => Box::new(RegionalVarianceFrameSelector::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    #[clap(name = "webp")]
+    #[cfg_attr(feature = "serde", serde(rename = "webp"))]
+    WebP,
+    Avif,
+}
+
+impl OutputFormat {
+    /// Guess the format from the extension of `path`
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "webp" => Some(Self::WebP),
+            "avif" => Some(Self::Avif),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+            Self::Avif => "image/avif",
+        }
+    }
+
+    pub fn supports_alpha(self) -> bool {
+        self != Self::Jpeg
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum VideoStreamSelector {
+    /// The stream uridecodebin3 selects by default
+    First,
+    /// The stream with the most pixels per frame
+    HighestResolution,
+    /// The video stream with the given index, counting only video streams
+    ///
+    /// Falls back to the default stream if there are fewer video streams.
+    StreamIndex(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum SeekPositions {
+    /// The first frame and frames at [`ThumbnailerConfig::seek_percentages`]
+    /// or [`ThumbnailerConfig::num_seek_positions`] positions, whichever
+    /// scores best
+    Percentages,
+    /// The frame at the start of the chapter with the given index
+    ///
+    /// Falls back to [`Self::Percentages`] if the file doesn't have that
+    /// many chapters.
+    Chapter(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[repr(u8)]
+pub enum Verbosity {
+    /// Only errors
+    Quiet,
+    /// Errors and warnings
+    Normal,
+    /// Also explain which stream, frames, and cover art are chosen
+    Verbose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum AudioFallback {
+    /// Fail without creating a thumbnail
+    Error,
+    /// Draw the waveform of the whole audio
+    ///
+    /// The audio has to be decoded for this, which takes a while for long
+    /// files.
+    Waveform,
+    /// Draw a mel-scale spectrogram of the whole audio
+    ///
+    /// Like [`Self::Waveform`], but shows which frequencies are present.
+    Spectrogram,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum PngCompression {
+    /// Fastest encoding with larger files
+    Fast,
+    /// The encoder's default
+    Default,
+    /// Smallest files with slower encoding
+    Best,
+}
+
+impl PngCompression {
+    /// Compression level between 0 and 100 passed to the encoder
+    ///
+    /// `None` keeps the encoder's default.
+    pub fn level(self) -> Option<u8> {
+        match self {
+            Self::Fast => Some(0),
+            Self::Default => None,
+            Self::Best => Some(100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThumbnailerProgress {
+    pub stage: ThumbnailerStage,
+    /// Estimated overall progress between 0 and 1
+    pub fraction: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailerStage {
+    Prerolling,
+    /// Seeking to candidate frame `.0` out of `.1`
+    SeekingFrame(usize, usize),
+    Encoding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum ScaleFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ScaleFilter> for image::imageops::FilterType {
+    fn from(value: ScaleFilter) -> Self {
+        match value {
+            ScaleFilter::Nearest => Self::Nearest,
+            ScaleFilter::Triangle => Self::Triangle,
+            ScaleFilter::CatmullRom => Self::CatmullRom,
+            ScaleFilter::Gaussian => Self::Gaussian,
+            ScaleFilter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+/// Images are first scaled to a multiple of the target size with the `coarse`
+/// filter and then to the target size with the `fine` filter
+///
+/// The multiple is [`ThumbnailerConfig::intermediate_scale_factor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScaleFilterPair {
+    pub coarse: ScaleFilter,
+    pub fine: ScaleFilter,
+}
+
+impl Default for ScaleFilterPair {
+    fn default() -> Self {
+        ScalePreset::Balanced.into()
+    }
+}
+
+/// Common [`ScaleFilterPair`]s trading speed for quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum ScalePreset {
+    /// Nearest neighbor for both steps
+    Fast,
+    /// Nearest neighbor followed by a linear filter
+    Balanced,
+    /// Lanczos followed by Catmull-Rom, sharpest but slowest
+    HighQuality,
+}
+
+impl From<ScalePreset> for ScaleFilterPair {
+    fn from(value: ScalePreset) -> Self {
+        let (coarse, fine) = match value {
+            ScalePreset::Fast => (ScaleFilter::Nearest, ScaleFilter::Nearest),
+            ScalePreset::Balanced => (ScaleFilter::Nearest, ScaleFilter::Triangle),
+            ScalePreset::HighQuality => (ScaleFilter::Lanczos3, ScaleFilter::CatmullRom),
+        };
+
+        Self { coarse, fine }
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use gio::glib;
+use zbus::object_server::SignalEmitter;
+
+use crate::{
+    DecoderThreads, Error, Result, SizeMode, ThumbnailFlavor, ThumbnailerConfig,
+    default_batch_parallelism,
+};
+
+/// Well-known name of the service on the session bus
+pub const BUS_NAME: &str = "org.gnome.GstThumbnailers";
+pub const OBJECT_PATH: &str = "/org/gnome/GstThumbnailers";
+
+/// Error codes of the `Error` signal, as defined by Tumbler in
+/// <https://gitlab.xfce.org/xfce/tumbler/-/blob/master/tumbler/tumbler-error.h>
+const ERROR_UNSUPPORTED: i32 = 0;
+const ERROR_INVALID_FORMAT: i32 = 2;
+const ERROR_UNSUPPORTED_FLAVOR: i32 = 5;
+
+/// Specialized thumbnailer service for Tumbler
+///
+/// Tumbler keeps owning `org.freedesktop.thumbnails.Thumbnailer1` and
+/// forwards `video/*` and `audio/*` files to this service. A fixed number of
+/// workers create the thumbnails and write them to the cache directory.
+pub struct GstThumbnailerService {
+    /// Directory with a subdirectory per flavor, `~/.cache/thumbnails` by
+    /// default
+    pub cache_dir: PathBuf,
+    /// Number of thumbnails created at the same time,
+    /// [`default_batch_parallelism`] by default
+    pub workers: usize,
+    /// Number of requests that wait for a worker before `Queue` fails
+    pub queue_depth: usize,
+}
+
+impl Default for GstThumbnailerService {
+    fn default() -> Self {
+        Self {
+            cache_dir: glib::user_cache_dir().join("thumbnails"),
+            workers: default_batch_parallelism(),
+            queue_depth: 64,
+        }
+    }
+}
+
+impl GstThumbnailerService {
+    /// Handle calls on the session bus until the process exits
+    pub fn run(self) -> Result<()> {
+        let _connection = self.connect()?;
+
+        loop {
+            std::thread::park();
+        }
+    }
+
+    /// Register as [`BUS_NAME`] on the session bus
+    ///
+    /// Calls are handled in the background until the returned connection is
+    /// dropped.
+    pub fn connect(self) -> Result<zbus::blocking::Connection> {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(self.queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let cache_dir = Arc::new(self.cache_dir);
+        let requests = Arc::new(Mutex::new(HashMap::new()));
+
+        // The workers stop once the thumbnailer is dropped with the
+        // connection
+        for _ in 0..self.workers.max(1) {
+            let worker = Worker {
+                receiver: receiver.clone(),
+                cache_dir: cache_dir.clone(),
+                requests: requests.clone(),
+            };
+            std::thread::spawn(move || worker.run());
+        }
+
+        let thumbnailer = Thumbnailer {
+            sender,
+            next_handle: AtomicU32::new(1),
+            requests,
+        };
+
+        zbus::blocking::connection::Builder::session()
+            .and_then(|builder| builder.name(BUS_NAME))
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, thumbnailer))
+            .and_then(|builder| builder.build())
+            .map_err(Error::other)
+    }
+}
+
+/// Requests that are queued or processed, by handle
+type Requests = Arc<Mutex<HashMap<u32, gio::Cancellable>>>;
+
+struct Thumbnailer {
+    sender: SyncSender<Job>,
+    next_handle: AtomicU32,
+    requests: Requests,
+}
+
+/// Interface Tumbler uses for thumbnailers of specific MIME types
+///
+/// Requests are processed in the order they were queued. The `urgent`
+/// argument of `Queue` is ignored.
+#[zbus::interface(name = "org.freedesktop.thumbnails.SpecializedThumbnailer1")]
+impl Thumbnailer {
+    /// Create a thumbnail for `uri` in the background
+    ///
+    /// Progress is reported via the `Started`, `Ready`, `Error`, and
+    /// `Finished` signals with the returned handle.
+    fn queue(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        uri: String,
+        mime_type: String,
+        flavor: String,
+        _urgent: bool,
+    ) -> zbus::fdo::Result<u32> {
+        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        let cancellable = gio::Cancellable::new();
+        self.requests
+            .lock()
+            .unwrap()
+            .insert(handle, cancellable.clone());
+
+        let job = Job {
+            emitter: emitter.to_owned(),
+            handle,
+            uri,
+            mime_type,
+            flavor,
+            cancellable,
+        };
+        self.sender.try_send(job).map_err(|err| {
+            self.requests.lock().unwrap().remove(&handle);
+            match err {
+                TrySendError::Full(_) => {
+                    zbus::fdo::Error::LimitsExceeded("Thumbnail queue is full".to_string())
+                }
+                TrySendError::Disconnected(_) => {
+                    zbus::fdo::Error::Failed("Thumbnail queue stopped".to_string())
+                }
+            }
+        })?;
+
+        Ok(handle)
+    }
+
+    /// Cancel the request with the handle returned by `Queue`
+    fn dequeue(&self, handle: u32) {
+        if let Some(cancellable) = self.requests.lock().unwrap().remove(&handle) {
+            cancellable.cancel();
+        }
+    }
+
+    /// A worker started processing the request
+    #[zbus(signal)]
+    async fn started(emitter: &SignalEmitter<'_>, handle: u32) -> zbus::Result<()>;
+
+    /// The thumbnail for `uri` was written to the cache
+    #[zbus(signal)]
+    async fn ready(emitter: &SignalEmitter<'_>, handle: u32, uri: &str) -> zbus::Result<()>;
+
+    /// No thumbnail could be created for `failed_uri`
+    #[zbus(signal)]
+    async fn error(
+        emitter: &SignalEmitter<'_>,
+        handle: u32,
+        failed_uri: &str,
+        error_code: i32,
+        message: &str,
+    ) -> zbus::Result<()>;
+
+    /// The request is done, after either `Ready` or `Error`
+    #[zbus(signal)]
+    async fn finished(emitter: &SignalEmitter<'_>, handle: u32) -> zbus::Result<()>;
+}
+
+/// Processes the jobs of all `Queue` calls
+struct Worker {
+    receiver: Arc<Mutex<Receiver<Job>>>,
+    cache_dir: Arc<PathBuf>,
+    requests: Requests,
+}
+
+impl Worker {
+    fn run(&self) {
+        loop {
+            // Don't keep the receiver locked while processing
+            let job = self.receiver.lock().unwrap().recv();
+            let Ok(job) = job else {
+                break;
+            };
+
+            // Dequeued jobs don't report anything
+            if !job.cancellable.is_cancelled() {
+                job.process(&self.cache_dir);
+            }
+            self.requests.lock().unwrap().remove(&job.handle);
+        }
+    }
+}
+
+/// Thumbnail requested by a single `Queue` call
+struct Job {
+    emitter: SignalEmitter<'static>,
+    handle: u32,
+    uri: String,
+    mime_type: String,
+    flavor: String,
+    cancellable: gio::Cancellable,
+}
+
+impl Job {
+    fn process(&self, cache_dir: &Path) {
+        let (emitter, handle, uri) = (&self.emitter, self.handle, self.uri.as_str());
+        emit("Started", Thumbnailer::started(emitter, handle));
+
+        match self.thumbnail(cache_dir) {
+            Ok(()) => emit("Ready", Thumbnailer::ready(emitter, handle, uri)),
+            Err((code, message)) => emit(
+                "Error",
+                Thumbnailer::error(emitter, handle, uri, code, &message),
+            ),
+        }
+
+        emit("Finished", Thumbnailer::finished(emitter, handle));
+    }
+
+    /// Write the thumbnail, failing with the code and message of the `Error`
+    /// signal
+    fn thumbnail(&self, cache_dir: &Path) -> std::result::Result<(), (i32, String)> {
+        let Some(flavor) = ThumbnailFlavor::from_name(&self.flavor) else {
+            let message = format!("Unsupported flavor {}", self.flavor);
+            return Err((ERROR_UNSUPPORTED_FLAVOR, message));
+        };
+        let config = ThumbnailerConfig {
+            size_mode: SizeMode::Fit { max: flavor.size() },
+            create_output_dir: true,
+            // Several workers decode at the same time
+            decoder_threads: DecoderThreads::Fixed(NonZeroU32::MIN),
+            ..Default::default()
+        };
+
+        let uri = &self.uri;
+        let output_path = crate::cache::thumbnail_path(cache_dir, uri, flavor);
+        let cancellable = Some(&self.cancellable);
+        let result = if self.mime_type.starts_with("video/") {
+            crate::thumbnail_video(uri, &output_path, &config, cancellable)
+        } else if self.mime_type.starts_with("audio/") {
+            crate::thumbnail_audio(uri, &output_path, &config, cancellable)
+        } else {
+            let message = format!("Unsupported MIME type {}", self.mime_type);
+            return Err((ERROR_UNSUPPORTED, message));
+        };
+
+        result.map_err(|err| (ERROR_INVALID_FORMAT, err.to_string()))
+    }
+}
+
+/// Emit a signal from a worker thread, only logging failures
+fn emit(signal: &str, emission: impl Future<Output = zbus::Result<()>>) {
+    if let Err(err) = zbus::block_on(emission) {
+        warn!("Failed to emit {signal} signal: {err}");
+    }
+}
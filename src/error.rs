@@ -1,24 +1,65 @@
 use std::fmt::Display;
 use std::panic::Location;
+use std::path::{Path, PathBuf};
 
 use gio::glib;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
     kind: ErrorKind,
     location: String,
+    /// Debug info of GStreamer error messages
+    debug: Option<String>,
 }
 
 impl Error {
+    #[track_caller]
+    pub(crate) fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            location: location(),
+            debug: None,
+        }
+    }
+
     #[track_caller]
     pub fn other(err: impl Display) -> Self {
         Self {
             kind: ErrorKind::Other(err.to_string()),
             location: location(),
+            debug: None,
         }
     }
+
+    #[track_caller]
+    pub(crate) fn output_dir(path: &Path, err: std::io::Error) -> Self {
+        Self {
+            kind: ErrorKind::OutputDir(path.to_path_buf(), err),
+            location: location(),
+            debug: None,
+        }
+    }
+
+    /// Error from a GStreamer error message, keeping its debug info
+    #[track_caller]
+    pub(crate) fn gst_message(message: &gst::message::Error) -> Self {
+        Self {
+            kind: ErrorKind::GLib(message.error()),
+            location: location(),
+            debug: message.debug().map(|debug| debug.to_string()),
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Additional debug info provided by GStreamer
+    pub fn debug(&self) -> Option<&str> {
+        self.debug.as_deref()
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -29,10 +70,22 @@ impl std::fmt::Display for Error {
             env!("CARGO_PKG_NAME"),
             self.location,
             self.kind
-        )
+        )?;
+
+        if let Some(debug) = &self.debug {
+            write!(f, "\n{debug}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    // The kind is already part of the message
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.kind.source()
     }
 }
-impl std::error::Error for Error {}
 
 #[derive(Debug)]
 pub enum ErrorKind {
@@ -40,6 +93,72 @@ pub enum ErrorKind {
     Other(String),
     StdIo(std::io::Error),
     GLib(glib::Error),
+    GstFlow(gst::FlowError),
+    /// Creating the directory for the output file failed
+    OutputDir(PathBuf, std::io::Error),
+    /// The input doesn't contain a video stream
+    NoVideoStream,
+    /// The input doesn't contain an audio stream
+    NoAudioStream,
+    /// The GStreamer element with this name isn't installed
+    MissingPlugin(String),
+    /// Seeking to a candidate frame failed
+    SeekFailed {
+        position: gst::ClockTime,
+        gst_error: String,
+    },
+    /// The pipeline didn't finish an operation within the configured
+    /// timeout
+    Timeout {
+        elapsed: std::time::Duration,
+        operation: String,
+    },
+    /// No candidate frame reached
+    /// [`crate::ThumbnailerConfig::min_variance_threshold`]
+    BlankFrames {
+        /// Highest variance of all candidate frames
+        best_variance: f32,
+    },
+}
+
+impl Clone for ErrorKind {
+    fn clone(&self) -> Self {
+        match self {
+            Self::GLibBool(err) => Self::GLibBool(err.clone()),
+            Self::Other(err) => Self::Other(err.clone()),
+            Self::StdIo(err) => Self::StdIo(clone_io_error(err)),
+            Self::GLib(err) => Self::GLib(err.clone()),
+            Self::GstFlow(err) => Self::GstFlow(*err),
+            Self::OutputDir(path, err) => Self::OutputDir(path.clone(), clone_io_error(err)),
+            Self::NoVideoStream => Self::NoVideoStream,
+            Self::NoAudioStream => Self::NoAudioStream,
+            Self::MissingPlugin(element) => Self::MissingPlugin(element.clone()),
+            Self::SeekFailed {
+                position,
+                gst_error,
+            } => Self::SeekFailed {
+                position: *position,
+                gst_error: gst_error.clone(),
+            },
+            Self::Timeout { elapsed, operation } => Self::Timeout {
+                elapsed: *elapsed,
+                operation: operation.clone(),
+            },
+            Self::BlankFrames { best_variance } => Self::BlankFrames {
+                best_variance: *best_variance,
+            },
+        }
+    }
+}
+
+/// Copy of an IO error with the same kind and message
+///
+/// The inner error of custom IO errors can't be cloned.
+fn clone_io_error(err: &std::io::Error) -> std::io::Error {
+    match err.raw_os_error() {
+        Some(code) => std::io::Error::from_raw_os_error(code),
+        None => std::io::Error::new(err.kind(), err.to_string()),
+    }
 }
 
 impl std::fmt::Display for ErrorKind {
@@ -49,6 +168,63 @@ impl std::fmt::Display for ErrorKind {
             Self::Other(err) => f.write_str(err),
             Self::StdIo(err) => f.write_str(&err.to_string()),
             Self::GLib(err) => f.write_str(&err.to_string()),
+            Self::GstFlow(err) => write!(f, "Appsink flow error: {err}"),
+            Self::NoVideoStream => f.write_str("No video stream found"),
+            Self::NoAudioStream => f.write_str("No audio stream found"),
+            Self::MissingPlugin(element) => match element_package(element) {
+                Some(package) => {
+                    write!(f, "Missing GStreamer plugin: {element} (install {package})")
+                }
+                None => write!(f, "Missing GStreamer plugin: {element}"),
+            },
+            Self::SeekFailed {
+                position,
+                gst_error,
+            } => write!(f, "Failed to seek to {position}: {gst_error}"),
+            Self::Timeout { elapsed, operation } => {
+                write!(f, "Timed out after {elapsed:.1?} during {operation}")
+            }
+            Self::BlankFrames { best_variance } => write!(
+                f,
+                "All candidate frames appear to be blank (highest variance {best_variance:.1})"
+            ),
+            Self::OutputDir(path, err) => match err.kind() {
+                std::io::ErrorKind::PermissionDenied => write!(
+                    f,
+                    "No permission to create output directory '{}'",
+                    path.display()
+                ),
+                // `create_dir_all` reports an existing file as `AlreadyExists`
+                std::io::ErrorKind::NotADirectory | std::io::ErrorKind::AlreadyExists => write!(
+                    f,
+                    "Can't create output directory '{}': a path component is a file",
+                    path.display()
+                ),
+                _ => write!(
+                    f,
+                    "Failed to create output directory '{}': {err}",
+                    path.display()
+                ),
+            },
+        }
+    }
+}
+
+impl std::error::Error for ErrorKind {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::GLibBool(err) => Some(err),
+            Self::StdIo(err) => Some(err),
+            Self::GLib(err) => Some(err),
+            Self::GstFlow(err) => Some(err),
+            Self::OutputDir(_, err) => Some(err),
+            Self::Other(_)
+            | Self::NoVideoStream
+            | Self::NoAudioStream
+            | Self::MissingPlugin(_)
+            | Self::SeekFailed { .. }
+            | Self::Timeout { .. }
+            | Self::BlankFrames { .. } => None,
         }
     }
 }
@@ -59,6 +235,7 @@ impl From<glib::BoolError> for Error {
         Self {
             kind: ErrorKind::GLibBool(value),
             location: location(),
+            debug: None,
         }
     }
 }
@@ -69,6 +246,7 @@ impl From<std::io::Error> for Error {
         Self {
             kind: ErrorKind::StdIo(value),
             location: location(),
+            debug: None,
         }
     }
 }
@@ -79,7 +257,31 @@ impl From<glib::Error> for Error {
         Self {
             kind: ErrorKind::GLib(value),
             location: location(),
+            debug: None,
+        }
+    }
+}
+
+impl From<gst::FlowError> for Error {
+    #[track_caller]
+    fn from(value: gst::FlowError) -> Self {
+        Self {
+            kind: ErrorKind::GstFlow(value),
+            location: location(),
+            debug: None,
+        }
+    }
+}
+
+/// Distribution package that contains the element
+fn element_package(element: &str) -> Option<&'static str> {
+    match element {
+        "capsfilter" | "fakesink" => Some("gstreamer1"),
+        "uridecodebin3" | "videoscale" | "videoconvert" | "appsrc" | "appsink" => {
+            Some("gstreamer1-plugins-base")
         }
+        "videoflip" => Some("gstreamer1-plugins-good"),
+        _ => None,
     }
 }
 
@@ -0,0 +1,73 @@
+/// Video frame in packed RGB format
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRef<'a> {
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per line including padding
+    pub stride: u32,
+}
+
+/// Strategy for rating candidate video frames
+///
+/// The frame with the highest score is used for the thumbnail.
+pub trait FrameSelector: Send + Sync {
+    fn score(&self, frame: &FrameRef) -> f32;
+}
+
+/// Variance over all RGB components
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VarianceFrameSelector;
+
+impl FrameSelector for VarianceFrameSelector {
+    fn score(&self, frame: &FrameRef) -> f32 {
+        crate::variance(frame)
+    }
+}
+
+/// Variance of the BT.709 luminance
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LuminanceVarianceFrameSelector;
+
+impl FrameSelector for LuminanceVarianceFrameSelector {
+    fn score(&self, frame: &FrameRef) -> f32 {
+        crate::luminance_variance(frame.data, frame.width, frame.stride, frame.height)
+    }
+}
+
+/// Sharpness measured via Sobel edge detection
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EdgeEnergyFrameSelector;
+
+impl FrameSelector for EdgeEnergyFrameSelector {
+    fn score(&self, frame: &FrameRef) -> f32 {
+        crate::edge_energy(frame.data, frame.width, frame.stride, frame.height)
+    }
+}
+
+/// Highest variance over all RGB components of any cell in a grid
+///
+/// Prefers frames where some region is detailed, even if most of the frame
+/// is a plain background.
+#[derive(Debug, Clone, Copy)]
+pub struct RegionalVarianceFrameSelector {
+    pub grid_rows: u8,
+    pub grid_cols: u8,
+}
+
+impl Default for RegionalVarianceFrameSelector {
+    fn default() -> Self {
+        Self {
+            grid_rows: 3,
+            grid_cols: 3,
+        }
+    }
+}
+
+impl FrameSelector for RegionalVarianceFrameSelector {
+    fn score(&self, frame: &FrameRef) -> f32 {
+        crate::regional_variance(frame, self.grid_rows, self.grid_cols)
+            .into_iter()
+            .fold(0., f32::max)
+    }
+}
@@ -0,0 +1,90 @@
+/// Scale dimensions to fit into a `max_size` × `max_size` box
+///
+/// The aspect ratio is preserved and images smaller than the box are not
+/// upscaled.
+pub fn scale_thumbnail_dimensions(width: f32, height: f32, max_size: u16) -> (u32, u32) {
+    fit_dimensions(width, height, max_size, max_size)
+}
+
+/// Like [`scale_thumbnail_dimensions`] for content with non-square pixels
+///
+/// The width is corrected by the pixel-aspect-ratio `par_numer / par_denom`
+/// so that the result has square pixels. Invalid ratios are treated as 1:1.
+pub fn scale_thumbnail_dimensions_with_par(
+    width: f32,
+    height: f32,
+    par_numer: i32,
+    par_denom: i32,
+    max_size: u16,
+) -> (u32, u32) {
+    fit_dimensions_with_par(width, height, par_numer, par_denom, max_size, max_size)
+}
+
+/// Scale dimensions to fit into a `max_width` × `max_height` box
+///
+/// The aspect ratio is preserved and images smaller than the box are not
+/// upscaled. Very thin images keep at least one pixel in each direction.
+pub fn fit_dimensions(width: f32, height: f32, max_width: u16, max_height: u16) -> (u32, u32) {
+    // avoid upscaling
+    let scale = f32::min(
+        1.0,
+        f32::min(max_width as f32 / width, max_height as f32 / height),
+    );
+    let scale_side = |side: f32| {
+        if side > 0. {
+            (side * scale).round().max(1.) as u32
+        } else {
+            0
+        }
+    };
+
+    (scale_side(width), scale_side(height))
+}
+
+/// Like [`fit_dimensions`] for content with non-square pixels
+///
+/// See [`scale_thumbnail_dimensions_with_par`].
+pub fn fit_dimensions_with_par(
+    width: f32,
+    height: f32,
+    par_numer: i32,
+    par_denom: i32,
+    max_width: u16,
+    max_height: u16,
+) -> (u32, u32) {
+    fit_dimensions(
+        display_width(width, par_numer, par_denom),
+        height,
+        max_width,
+        max_height,
+    )
+}
+
+/// Scale dimensions so that the shorter side is exactly `size`
+///
+/// The aspect ratio is preserved. Unlike [`fit_dimensions`] this upscales
+/// small images so that the result can be cropped to a `size` × `size`
+/// square.
+pub fn fill_dimensions(width: f32, height: f32, size: u16) -> (u32, u32) {
+    if width <= 0. || height <= 0. {
+        return (0, 0);
+    }
+
+    let scale = size as f32 / f32::min(width, height);
+    let new_width = (width * scale).round() as u32;
+    let new_height = (height * scale).round() as u32;
+
+    (new_width, new_height)
+}
+
+/// Width of content with the pixel-aspect-ratio `par_numer / par_denom`
+/// when displayed with square pixels
+///
+/// Invalid ratios are treated as 1:1.
+pub fn display_width(width: f32, par_numer: i32, par_denom: i32) -> f32 {
+    if par_numer > 0 && par_denom > 0 {
+        width * par_numer as f32 / par_denom as f32
+    } else {
+        width
+    }
+}
@@ -1,19 +1,52 @@
+#[macro_use]
+mod logging;
+
+mod batch;
+mod cache;
 mod cli;
+mod config;
+#[cfg(feature = "dbus")]
+mod dbus;
 mod error;
+mod frame_selector;
+pub mod geometry;
+mod memory_source;
+mod metadata;
+#[cfg(feature = "simd")]
+mod simd;
+mod spectrogram;
+mod tag;
+mod template;
+mod thumbnailer;
+mod waveform;
 
 use std::ffi::OsString;
-use std::io::Write;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-
+use std::num::{NonZeroU8, NonZeroU32};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant};
+
+pub use batch::*;
+pub use cache::*;
 use clap::Parser;
+pub use config::*;
+#[cfg(feature = "dbus")]
+pub use dbus::*;
 pub use error::*;
+pub use frame_selector::*;
+use geometry::display_width;
 use gio::glib;
 use gio::prelude::*;
 use gst::prelude::*;
-
-const SCALE_FILTER1: image::imageops::FilterType = image::imageops::FilterType::Nearest;
-const SCALE_FILTER2: image::imageops::FilterType = image::imageops::FilterType::Triangle;
+use memory_source::{APPSRC_URI, MemorySource};
+pub use metadata::*;
+#[cfg(feature = "simd")]
+pub use simd::variance_simd;
+pub use tag::*;
+pub use template::*;
+pub use thumbnailer::*;
 
 fn check_plugins() -> Result<()> {
     let needed = [
@@ -42,22 +75,67 @@ fn check_plugins() -> Result<()> {
     }
 }
 
-fn init<I, T>(args: I) -> Result<cli::Args>
-where
-    I: IntoIterator<Item = T>,
-    T: Into<OsString> + Clone,
-{
+/// Fail with an install hint if any of the elements is unavailable
+fn check_required_elements(elements: &[&str]) -> Result<()> {
+    for &name in elements {
+        if gst::ElementFactory::find(name).is_none() {
+            return Err(Error::new(ErrorKind::MissingPlugin(name.to_string())));
+        }
+    }
+
+    Ok(())
+}
+
+/// Oldest GStreamer version as `(major, minor, micro, nano)`
+///
+/// 1.22 merged `videoconvert` and `videoscale` into the `videoconvertscale`
+/// plugin that [`check_plugins`] looks for.
+const MINIMUM_GST_VERSION: (u32, u32, u32, u32) = (1, 22, 0, 0);
+
+/// URI of stdin, read by `fdsrc`
+///
+/// Pipes can't be seeked, so only the first frame is used for videos.
+pub const STDIN_URI: &str = "fd://0";
+
+fn check_gstreamer_version(min: (u32, u32, u32, u32)) -> Result<()> {
+    let found = gst::version();
+    if found < min {
+        return Err(Error::other(format!(
+            "GStreamer {min:?} required, found {found:?}"
+        )));
+    }
+
+    Ok(())
+}
+
+fn init(config: &ThumbnailerConfig) -> Result<()> {
     gst::init().unwrap();
+    #[cfg(feature = "gst-log")]
+    logging::install_gst_log_bridge();
+    logging::set_verbosity(config.verbosity);
+
+    // Also rejects NaN
+    if !(1.0..).contains(&config.intermediate_scale_factor) {
+        return Err(Error::other(format!(
+            "Intermediate scale factor has to be at least 1, got {}",
+            config.intermediate_scale_factor
+        )));
+    }
+    if let Some(percentage) = config
+        .seek_percentages
+        .iter()
+        .flatten()
+        .find(|&&percentage| percentage > 100)
+    {
+        return Err(Error::other(format!(
+            "Seek percentages have to be at most 100, got {percentage}"
+        )));
+    }
 
+    check_gstreamer_version(MINIMUM_GST_VERSION)?;
     check_plugins()?;
 
-    // This could be solved in a cleaner way when GStreamer adds support for
-    // sorting decoder factories in uridecodebin3.
-    // See: https://gitlab.freedesktop.org/gstreamer/gstreamer/-/issues/959
-    // and  https://gitlab.freedesktop.org/gstreamer/gstreamer/-/merge_requests/9672
-    disable_hardware_decoders();
-
-    Ok(cli::Args::parse_from(args))
+    Ok(())
 }
 
 pub fn main_audio_thumbnailer<I, T>(args: I) -> Result<()>
@@ -65,14 +143,24 @@ where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
-    let args = init(args)?;
-
-    get_audio_thumbnail_source(&args.source.uri())?
-        .ok_or(Error::other("No tag image found"))?
-        .write_png(&args.output, args.size)
-        .unwrap();
-
-    Ok(())
+    let args = cli::Args::parse_from(args);
+    if args.is_cached()? {
+        return Ok(());
+    }
+    let (uri, config) = (args.source.uri()?, args.config());
+
+    match &args.output()? {
+        cli::OutputPath::Literal(path) => thumbnail_audio(&uri, path, &config, None),
+        cli::OutputPath::Template(template) => {
+            init(&config)?;
+            audio_thumbnail_source(&uri, &config, None)?.write_image_to_template(
+                template,
+                &uri,
+                &config,
+                &ThumbMetadata::for_source(&uri),
+            )
+        }
+    }
 }
 
 pub fn main_video_thumbnailer<I, T>(args: I) -> Result<()>
@@ -80,17 +168,223 @@ where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
-    let args = init(args)?;
+    let args = cli::Args::parse_from(args);
+    if args.is_cached()? {
+        return Ok(());
+    }
+    let (uri, config) = (args.source.uri()?, args.config());
+
+    match &args.output()? {
+        cli::OutputPath::Literal(path) => thumbnail_video(&uri, path, &config, None),
+        cli::OutputPath::Template(template) => {
+            init(&config)?;
+            let (source, metadata) = get_video_thumbnail_source(
+                &uri,
+                None,
+                &config,
+                config.decoder_threads.threads(),
+                None,
+            )?;
+            source.write_image_to_template(template, &uri, &config, &metadata)
+        }
+    }
+}
 
-    get_video_thumbnail_source(&args.source.uri(), args.size)?
-        .write_png(&args.output, args.size)
-        .unwrap();
+pub fn thumbnail_audio(
+    input_uri: &str,
+    output_path: &Path,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<()> {
+    init(config)?;
+
+    create_audio_thumbnail(input_uri, output_path, config, cancellable)?;
+    Ok(())
+}
 
+pub fn thumbnail_video(
+    input_uri: &str,
+    output_path: &Path,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<()> {
+    init(config)?;
+
+    create_video_thumbnail(
+        input_uri,
+        output_path,
+        config,
+        config.decoder_threads.threads(),
+        cancellable,
+    )?;
     Ok(())
 }
 
-fn get_audio_thumbnail_source(input_uri: &str) -> Result<Option<ThumbnailSource>> {
-    let pipeline = Pipeline::new();
+/// Like [`thumbnail_audio`] but returns the encoded image instead of writing it
+pub fn thumbnail_audio_to_bytes(
+    input_uri: &str,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<Vec<u8>> {
+    init(config)?;
+
+    audio_thumbnail_source(input_uri, config, cancellable)?
+        .encode_to_bytes_with_metadata(config, &ThumbMetadata::for_source(input_uri))
+}
+
+/// Like [`thumbnail_video`] but returns the encoded image instead of writing it
+pub fn thumbnail_video_to_bytes(
+    input_uri: &str,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<Vec<u8>> {
+    init(config)?;
+
+    let (source, metadata) = get_video_thumbnail_source(
+        input_uri,
+        None,
+        config,
+        config.decoder_threads.threads(),
+        cancellable,
+    )?;
+    source.encode_to_bytes_with_metadata(config, &metadata)
+}
+
+/// Extract the thumbnail image from media data that is already in memory
+///
+/// `mime_hint`, like `video/webm`, skips the detection of the container
+/// format.
+pub fn thumbnail_video_from_bytes(
+    data: &[u8],
+    mime_hint: Option<&str>,
+    size: u16,
+) -> Result<ThumbnailSource> {
+    let config = ThumbnailerConfig {
+        size_mode: SizeMode::Fit { max: size },
+        ..Default::default()
+    };
+    init(&config)?;
+
+    let memory_source = MemorySource::new(data, mime_hint);
+    let (source, _) = get_video_thumbnail_source(
+        APPSRC_URI,
+        Some(&memory_source),
+        &config,
+        config.decoder_threads.threads(),
+        None,
+    )?;
+    Ok(source)
+}
+
+fn create_audio_thumbnail(
+    input_uri: &str,
+    output_path: &Path,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<ThumbnailMeta> {
+    let (width, height) = audio_thumbnail_source(input_uri, config, cancellable)?.write_image(
+        output_path,
+        config,
+        &ThumbMetadata::for_source(input_uri),
+    )?;
+
+    Ok(ThumbnailMeta {
+        width,
+        height,
+        source_frame_timestamp: None,
+    })
+}
+
+fn create_video_thumbnail(
+    input_uri: &str,
+    output_path: &Path,
+    config: &ThumbnailerConfig,
+    decoder_threads: NonZeroU32,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<ThumbnailMeta> {
+    let (source, metadata) =
+        get_video_thumbnail_source(input_uri, None, config, decoder_threads, cancellable)?;
+    let (width, height) = source.write_image(output_path, config, &metadata)?;
+
+    Ok(ThumbnailMeta {
+        width,
+        height,
+        source_frame_timestamp: metadata.movie_offset,
+    })
+}
+
+/// Embedded cover art or else a cover art file next to the audio file
+///
+/// Without either, [`ThumbnailerConfig::audio_fallback`] decides what
+/// happens.
+fn audio_thumbnail_source(
+    input_uri: &str,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<ThumbnailSource> {
+    if let Some(source) = get_audio_thumbnail_source(input_uri, config, cancellable)? {
+        return Ok(source);
+    }
+
+    let Some(path) = find_cover_art_file(input_uri, config) else {
+        return match config.audio_fallback {
+            AudioFallback::Error => Err(Error::other("No tag image found")),
+            AudioFallback::Waveform => waveform::waveform_source(input_uri, config, cancellable),
+            AudioFallback::Spectrogram => {
+                spectrogram::spectrogram_source(input_uri, config, cancellable)
+            }
+        };
+    };
+    let data = std::fs::read(path)?;
+    let sample = gst::Sample::builder()
+        .buffer(&gst::Buffer::from_mut_slice(data))
+        .build();
+
+    Ok(ThumbnailSource::CoverArt(sample))
+}
+
+/// Names of cover art files in the order they are preferred
+const COVER_ART_FILE_NAMES: [&str; 4] = ["folder.jpg", "cover.jpg", "cover.png", "artwork.jpg"];
+
+fn find_cover_art_file(input_uri: &str, config: &ThumbnailerConfig) -> Option<PathBuf> {
+    let audio_path = gio::File::for_uri(input_uri).path()?;
+    let audio_dir = audio_path.parent()?;
+
+    for dir in &config.cover_art_fallback_dirs {
+        let Ok(entries) = std::fs::read_dir(audio_dir.join(dir)) else {
+            continue;
+        };
+        let files = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+
+        // Names are compared case-insensitively since files like
+        // `Folder.jpg` are common
+        for name in COVER_ART_FILE_NAMES {
+            if let Some(path) = files.iter().find(|path| {
+                path.file_name()
+                    .is_some_and(|n| n.eq_ignore_ascii_case(name))
+            }) {
+                return Some(path.clone());
+            }
+        }
+    }
+
+    None
+}
+
+fn get_audio_thumbnail_source(
+    input_uri: &str,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<Option<ThumbnailSource>> {
+    check_cancelled(cancellable)?;
+    check_required_elements(&["uridecodebin3", "fakesink"])?;
+
+    let _decoders = DecoderRegistry::for_config(config);
+    let pipeline = Pipeline::new("gst-audio-thumbnailer");
 
     // Source
     let uridecodebin = gst::ElementFactory::make("uridecodebin3")
@@ -105,62 +399,111 @@ fn get_audio_thumbnail_source(input_uri: &str) -> Result<Option<ThumbnailSource>
     pipeline.add_many([&uridecodebin, &fakesink])?;
 
     // Connect dynamic pad from uridecodebin3 to fakesink
-    uridecodebin.connect_pad_added(move |_, src_pad| {
-        let sink_pad = fakesink.static_pad("sink").unwrap();
-        if !sink_pad.is_linked() {
-            src_pad.link(&sink_pad).unwrap();
-        }
-    });
+    let has_audio = Arc::new(AtomicBool::new(false));
+    uridecodebin.connect_pad_added(glib::clone!(
+        #[strong]
+        has_audio,
+        move |_, src_pad| {
+            if src_pad
+                .stream()
+                .is_some_and(|stream| stream.stream_type() == gst::StreamType::AUDIO)
+            {
+                has_audio.store(true, Ordering::Relaxed);
+            }
 
-    // Get stream initialized
-    match pipeline.set_state(gst::State::Paused) {
-        Ok(gst::StateChangeSuccess::NoPreroll) => {
-            return Err(Error::other(
-                "Error: thumbnails of live streams make little sense",
-            ));
-        }
-        Err(_) => {
-            return Err(Error::other(state_change_error_details(&pipeline)));
+            let sink_pad = fakesink.static_pad("sink").unwrap();
+            if !sink_pad.is_linked() {
+                src_pad.link(&sink_pad).unwrap();
+            }
         }
-        Ok(_) => {}
-    }
+    ));
 
-    // Wait until stream is initialized
-    while let Some(message) = pipeline.bus().unwrap().timed_pop(gst::ClockTime::NONE) {
-        match message.view() {
-            gst::MessageView::AsyncDone(_) => return Ok(None),
-            gst::MessageView::Error(err) => {
-                return Err(Error::other(format!(
-                    "Error: Failed pre-rolling pipeline: {err}"
-                )));
-            }
-            gst::MessageView::Tag(tag) => {
-                if let Some(sample) = get_thumbnail_from_tag(tag) {
-                    return Ok(Some(ThumbnailSource::CoverArt(sample)));
-                }
+    // Get stream initialized
+    let cover_art = preroll(
+        &pipeline,
+        config,
+        cancellable,
+        |message| {
+            if let gst::MessageView::Tag(tag) = message.view()
+                && let Some(sample) = select_cover_art(get_all_thumbnail_candidates(tag), config)
+            {
+                return Ok(Some(ThumbnailSource::CoverArt(sample)));
             }
-            _ => {}
-        }
+            Ok(None)
+        },
+        || has_audio.store(false, Ordering::Relaxed),
+    )?;
+
+    // All pads are added before prerolling finishes
+    if cover_art.is_none() && !has_audio.load(Ordering::Relaxed) {
+        return Err(Error::new(ErrorKind::NoAudioStream));
     }
 
-    Ok(None)
+    Ok(cover_art)
 }
 
-fn get_video_thumbnail_source(input_uri: &str, thumbnail_size: u16) -> Result<ThumbnailSource> {
-    let pipeline = Pipeline::new();
+/// `decoder_threads` replaces [`ThumbnailerConfig::decoder_threads`], which
+/// is resolved differently in batches
+fn get_video_thumbnail_source(
+    input_uri: &str,
+    memory_source: Option<&MemorySource>,
+    config: &ThumbnailerConfig,
+    decoder_threads: NonZeroU32,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<(ThumbnailSource, ThumbMetadata)> {
+    check_cancelled(cancellable)?;
+    check_required_elements(&[
+        "uridecodebin3",
+        "videoscale",
+        "videoconvert",
+        "capsfilter",
+        "appsink",
+    ])?;
+
+    let decoders = DecoderRegistry::for_config(config);
+    let pipeline = Pipeline::new("gst-video-thumbnailer");
 
     // Source
     let uridecodebin = gst::ElementFactory::make("uridecodebin3")
         .property("uri", input_uri)
         .build()?;
+    if let Some(memory_source) = memory_source {
+        check_required_elements(&["appsrc"])?;
+        let memory_source = memory_source.clone();
+        uridecodebin.connect_closure(
+            "source-setup",
+            false,
+            glib::closure!(move |_: &gst::Element, source: &gst::Element| {
+                memory_source.setup(source);
+            }),
+        );
+    }
 
     // Filters
     let videoscale = gst::ElementFactory::make("videoscale").build()?;
     let videoconvert = gst::ElementFactory::make("videoconvert").build()?;
     let capsfilter = gst::ElementFactory::make("capsfilter").build()?;
-    let videoflip = gst::ElementFactory::make("videoflip")
-        .property("video-direction", gst_video::VideoOrientationMethod::Auto)
-        .build()?;
+    let videoflip = if config.apply_rotation {
+        check_required_elements(&["videoflip"])?;
+        Some(
+            gst::ElementFactory::make("videoflip")
+                .property(
+                    "video-direction",
+                    config
+                        .forced_rotation
+                        .unwrap_or(gst_video::VideoOrientationMethod::Auto),
+                )
+                .build()?,
+        )
+    } else {
+        None
+    };
+    let tonemap = if config.tone_map_hdr {
+        check_required_elements(&["tonemap"])?;
+        Some(make_tonemap()?)
+    } else {
+        None
+    };
 
     // Sink
     let appsink = gst_app::AppSink::builder()
@@ -169,23 +512,23 @@ fn get_video_thumbnail_source(input_uri: &str, thumbnail_size: u16) -> Result<Th
         .max_buffers(1)
         .build();
 
-    pipeline.add_many([
-        &uridecodebin,
-        &videoscale,
-        &videoconvert,
-        &capsfilter,
-        &videoflip,
-        appsink.upcast_ref(),
-    ])?;
+    let filters = [
+        Some(&videoscale),
+        Some(&videoconvert),
+        tonemap.as_ref(),
+        Some(&capsfilter),
+        videoflip.as_ref(),
+        Some(appsink.upcast_ref::<gst::Element>()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    pipeline.add(&uridecodebin)?;
+    pipeline.add_many(&filters)?;
 
     // Static links
-    gst::Element::link_many([
-        &videoscale,
-        &videoconvert,
-        &capsfilter,
-        &videoflip,
-        appsink.upcast_ref(),
-    ])?;
+    gst::Element::link_many(&filters)?;
 
     // Manually set number of worker threads for decoders in order to reduce memory
     // usage on setups with many cores, see
@@ -194,38 +537,15 @@ fn get_video_thumbnail_source(input_uri: &str, thumbnail_size: u16) -> Result<Th
         "deep-element-added",
         false,
         glib::closure!(
-            |_uridecodebin: &gst::Element, _bin: &gst::Bin, element: &gst::Element| {
-                let Some(factory) = element.factory() else {
-                    return;
-                };
-
-                match factory.name().as_str() {
-                    // WARNING!
-                    // Be careful adding support for new elements in the future here. Make sure
-                    // your tests have covered newly added code, since it's easy to use an incorrect
-                    // type for the "number of threads" property. Some elements use an unsigned
-                    // integer, others a signed integer. Mixing them up will result
-                    // in a runtime crash with no compiler warning.
-                    factory_name if factory_name.starts_with("avdec_") => {
-                        let gobject_class = element.class();
-                        if gobject_class.find_property("max-threads").is_some() {
-                            element.set_property("max-threads", 1i32);
-                        }
-                    }
-                    "dav1ddec" => {
-                        element.set_property("n-threads", 1u32);
-                    }
-                    "vp8dec" | "vp9dec" => {
-                        element.set_property("threads", 1u32);
-                    }
-                    _ => (),
-                }
+            move |_: &gst::Element, _: &gst::Bin, element: &gst::Element| {
+                configure_decoder_threads(element, decoder_threads);
             }
         ),
     );
 
-    // This error message will be replace once pads are detected
-    let source_link_status = Arc::new(Mutex::new(Err(Error::other("No pad added for source."))));
+    let size_mode = config.size_mode;
+    // This error will be replaced once a video pad is linked
+    let source_link_status = Arc::new(Mutex::new(Err(Error::new(ErrorKind::NoVideoStream))));
     uridecodebin.connect_pad_added(glib::clone!(
         #[strong]
         source_link_status,
@@ -233,34 +553,38 @@ fn get_video_thumbnail_source(input_uri: &str, thumbnail_size: u16) -> Result<Th
             let link_source = || {
                 let stream = src_pad.stream().unwrap();
                 if stream.stream_type() != gst::StreamType::VIDEO {
-                    return Err(Error::other(format!(
-                        "Stream is of type '{}' instead of 'video'",
-                        stream.stream_type()
-                    )));
+                    return Err(Error::new(ErrorKind::NoVideoStream));
                 }
                 let caps = stream.caps().unwrap();
                 let s = caps.structure(0).unwrap();
 
-                let mut width = s.get::<i32>("width").unwrap() as f32;
+                let width = s.get::<i32>("width").unwrap() as f32;
                 let height = s.get::<i32>("height").unwrap() as f32;
-                if let Some(par) = s
+                let par = s
                     .get_optional::<gst::Fraction>("pixel-aspect-ratio")
                     .map_err(Error::other)?
-                {
-                    width *= par.numer() as f32 / par.denom() as f32;
-                }
+                    .unwrap_or(gst::Fraction::new(1, 1));
 
-                let (new_width, new_height) =
-                    scale_thumbnail_dimensions(width, height, thumbnail_size);
+                let (new_width, new_height) = size_mode
+                    .scaled_dimensions(display_width(width, par.numer(), par.denom()), height);
+                verbose!("Video is {width}×{height}, scaling to {new_width}×{new_height}");
 
-                let caps = gst::Caps::builder("video/x-raw")
+                let mut caps = gst::Caps::builder("video/x-raw")
                     .field("format", "RGB")
                     .field("width", new_width as i32)
                     .field("height", new_height as i32)
-                    .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
-                    .build();
+                    .field("pixel-aspect-ratio", gst::Fraction::new(1, 1));
+
+                // By default videoconvert only changes the bit depth, which
+                // saturates the colors of HDR and 10-bit wide gamut video
+                if is_hdr(s) {
+                    verbose!("Converting HDR video to sRGB");
+                    videoconvert.set_property_from_str("gamma-mode", "remap");
+                    videoconvert.set_property_from_str("primaries-mode", "fast");
+                    caps = caps.field("colorimetry", "sRGB");
+                }
 
-                capsfilter.set_property("caps", caps);
+                capsfilter.set_property("caps", caps.build());
 
                 // Link source pad to sink of first filter
                 let sink_pad = videoscale.static_pad("sink").unwrap();
@@ -279,343 +603,1414 @@ fn get_video_thumbnail_source(input_uri: &str, thumbnail_size: u16) -> Result<Th
         }
     ));
 
-    // Get stream initialized
-    match pipeline.set_state(gst::State::Paused) {
-        Ok(gst::StateChangeSuccess::NoPreroll) => {
-            return Err(Error::other(
-                "Error: thumbnails of live streams make little sense",
-            ));
-        }
-        Err(_) => {
-            return Err(Error::other(state_change_error_details(&pipeline)));
-        }
-        Ok(_) => {}
-    }
+    config.report_progress(ThumbnailerStage::Prerolling, 0.);
+    pipeline.dump_dot_if_enabled("gst_video_thumbnailer_null");
 
-    // Wait until stream is initialized
-    while let Some(message) = pipeline.bus().unwrap().timed_pop(gst::ClockTime::NONE) {
-        match message.view() {
-            gst::MessageView::StreamsSelected(_) => {
-                // This is fired after all pads have been connected. So check here if a usable
-                // pad has been connected.
-                std::mem::replace(&mut *source_link_status.lock().unwrap(), Ok(()))?;
-            }
-            gst::MessageView::AsyncDone(_) => {
-                // We didn't find a stored thumbnail/cover, so continue with extracting frames
-                break;
-            }
-            gst::MessageView::Error(err) => {
-                return Err(Error::other(format!("Failed pre-rolling pipeline: {err}")));
-            }
-            gst::MessageView::Tag(tag) => {
-                if let Some(sample) = get_thumbnail_from_tag(tag) {
-                    return Ok(ThumbnailSource::CoverArt(sample));
+    // Get stream initialized
+    let cover_art = preroll(
+        &pipeline,
+        config,
+        cancellable,
+        |message| {
+            match message.view() {
+                gst::MessageView::StreamsSelected(_) => {
+                    // This is fired after all pads have been connected. So check here if a usable
+                    // pad has been connected.
+                    std::mem::replace(&mut *source_link_status.lock().unwrap(), Ok(()))?;
+                }
+                gst::MessageView::StreamCollection(collection) => {
+                    let collection = collection.stream_collection();
+                    if let Some(stream_id) =
+                        select_video_stream(config.video_stream_selector, &collection)
+                    {
+                        verbose!("Selecting video stream {stream_id}");
+                        uridecodebin
+                            .send_event(gst::event::SelectStreams::new([stream_id.as_str()]));
+                    }
+                }
+                gst::MessageView::Tag(tag) => {
+                    if let Some(sample) = get_thumbnail_from_tag(tag) {
+                        return Ok(Some(sample));
+                    }
                 }
+                _ => {}
             }
-            _ => {}
-        }
+            Ok(None)
+        },
+        // The pads of the failed attempt are gone
+        || *source_link_status.lock().unwrap() = Err(Error::new(ErrorKind::NoVideoStream)),
+    )?;
+
+    if let Some(sample) = cover_art {
+        verbose!("Using embedded cover art instead of a frame");
+        return Ok((
+            ThumbnailSource::CoverArt(sample),
+            ThumbMetadata::for_source(input_uri),
+        ));
     }
 
-    pipeline.debug_to_dot_file_with_ts(
-        gst::DebugGraphDetails::all(),
-        "gst_video_thumbnailer_paused",
-    );
+    pipeline.dump_dot_if_enabled("gst_video_thumbnailer_paused");
+    // The decoders exist now
+    drop(decoders);
 
-    let duration = if let Some(duration) = pipeline.query_duration::<gst::ClockTime>() {
+    let queried_duration = pipeline.query_duration::<gst::ClockTime>();
+    let duration = if let Some(duration) = queried_duration {
+        verbose!("Video length is {duration}");
         duration
     } else {
-        eprintln!("Failed to get video length.");
+        warn!("Failed to get video length.");
         gst::ClockTime::ZERO
     };
 
-    // Determine position in video we want to take as thumbnail
-    let seek_at = if duration > 180.seconds() {
-        // For long videos, take frames at 10%, 15%, 20%, 25%, 30% of the
-        // video This only uses the first third of the video to not spoiler
-        // films
-        [10, 15, 20, 25, 30]
+    // Streams that can't be seeked, like some MPEG-TS or data piped to
+    // stdin, only provide the preroll frame
+    let mut seeking = gst::query::Seeking::new(gst::Format::Time);
+    let seekable = input_uri != STDIN_URI && pipeline.query(&mut seeking) && seeking.result().0;
+    if !seekable {
+        verbose!("Video is not seekable, only the first frame is used");
+    }
+
+    let metadata = ThumbMetadata {
+        chapters: chapter_start_times(&appsink),
+        duration: queried_duration,
+        dimensions: source_dimensions(&videoscale),
+        ..ThumbMetadata::for_source(input_uri)
+    };
+
+    // Determine positions in video we want to take candidate frames at
+    let chapter_start = match config.seek_positions {
+        SeekPositions::Chapter(index) if seekable => metadata.chapters.get(index).copied(),
+        _ => None,
+    };
+    let seek_at = if let Some(start) = chapter_start {
+        verbose!("Using frame at the start of the chapter at {start}");
+        vec![start]
+    } else if !seekable {
+        Vec::new()
     } else {
-        // For short videos, sample from the complete video
-        [10, 20, 30, 60, 90]
+        let percentages = match &config.seek_percentages {
+            Some(percentages) => percentages.iter().copied().map(u64::from).collect(),
+            None => seek_percentages(duration, config.num_seek_positions),
+        };
+        percentages
+            .into_iter()
+            .map(|percentage| duration.mul_div_ceil(percentage, 100).unwrap())
+            .collect()
     };
 
-    let mut samples = vec![appsink.pull_preroll()?];
+    // Only the best frame so far and the current one are kept in memory. The
+    // buffer of the worse one is reused for the next frame. The start of a
+    // chapter is used even if the first frame would score better.
+    let mut best = None;
+    if chapter_start.is_none() {
+        let frame = ScoredFrame::new(&pull_preroll(&appsink)?, config, Vec::new())?;
+        verbose!(
+            "Frame at the start scored {}{}",
+            frame.score,
+            if frame.black { ", likely black" } else { "" }
+        );
+        best = Some(frame);
+    }
+    let mut spare_buf = Vec::new();
+    // The best frame doesn't need to have the highest variance
+    let mut best_variance = best.as_ref().and_then(|frame| frame.variance);
+
+    let mut seek_error = None;
+    let mut seek_succeeded = false;
 
     // Pull frames at seek positions
-    for percentage in seek_at {
-        let seek_to = duration.mul_div_ceil(percentage, 100).unwrap();
-
-        // Seek to calculated position
-        //
-        // Allow to fail in the hope that we still get a frame
-        if pipeline
-            .seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT, seek_to)
-            .is_err()
-        {
-            eprintln!("Failed to seek to {seek_to}");
-        }
-
-        // Wait until seek is finished
-        let msg = pipeline.bus().unwrap().timed_pop_filtered(
-            gst::ClockTime::NONE,
-            &[gst::MessageType::Error, gst::MessageType::AsyncDone],
+    let num_steps = seek_at.len() + 2;
+    for (i, seek_to) in seek_at.iter().copied().enumerate() {
+        check_cancelled(cancellable)?;
+        config.report_progress(
+            ThumbnailerStage::SeekingFrame(i + 1, seek_at.len()),
+            (i + 1) as f64 / num_steps as f64,
         );
 
-        if let Some(gst::MessageView::Error(err)) = msg.as_ref().map(|msg| msg.view()) {
-            return Err(Error::other(format!(
-                "Error: Failed pre-rolling pipeline after seek: {err}"
-            )));
+        // A failed seek would only give us the previous frame again
+        if let Err(err) = seek(&pipeline, seek_to) {
+            verbose!("Seeking to {seek_to} failed: {err}");
+            seek_error = Some(err);
+            continue;
         }
-
-        samples.push(appsink.pull_preroll()?);
+        seek_succeeded = true;
+        wait_for_seek(&pipeline, config, &format!("seek to {seek_to}"))?;
+
+        let frame = ScoredFrame::new(
+            &pull_preroll(&appsink)?,
+            config,
+            std::mem::take(&mut spare_buf),
+        )?;
+        verbose!(
+            "Frame at {seek_to} scored {}{}",
+            frame.score,
+            if frame.black { ", likely black" } else { "" }
+        );
+        best_variance = best_variance
+            .into_iter()
+            .chain(frame.variance)
+            .reduce(f32::max);
+        spare_buf = if best.as_ref().is_none_or(|best| frame.is_better_than(best)) {
+            best.replace(frame)
+                .map(|frame| frame.data)
+                .unwrap_or_default()
+        } else {
+            frame.data
+        };
     }
 
-    // Use sample with highest variance
-    let (sample, _) = samples
-        .into_iter()
-        .filter_map(|x| {
-            let caps = x.caps().unwrap();
-            let info = gst_video::VideoInfo::from_caps(caps).ok()?;
-
-            let data = x.buffer()?.map_readable().ok()?;
-            let var = variance(&data, info.width(), info.stride()[0] as u32, info.height());
-            drop(data);
-
-            Some((x, var))
-        })
-        .max_by(|(_, var1), (_, var2)| var1.partial_cmp(var2).unwrap())
-        .unwrap();
+    let best = match (best, seek_error) {
+        // The thumbnail would just be the preroll frame
+        (_, Some(err)) if !seek_succeeded => return Err(err),
+        (Some(best), _) => best,
+        (None, _) => return Err(Error::other("No candidate frame")),
+    };
 
-    let caps = sample.caps().unwrap();
-    let info = gst_video::VideoInfo::from_caps(caps)?;
-    let width = info.width();
-    let height = info.height();
-    let stride = info.stride()[0] as usize;
+    check_not_blank(config, best_variance)?;
+    verbose!("Using frame with score {}", best.score);
 
-    let new_stride = width as usize * 3;
-    let sample_map = sample.buffer().unwrap().map_readable()?;
+    config.report_progress(
+        ThumbnailerStage::Encoding,
+        (num_steps - 1) as f64 / num_steps as f64,
+    );
 
-    // Get rid of padding after stride
-    let mut buf = vec![0; height as usize * new_stride];
-    for (out_line, in_line) in Iterator::zip(
-        buf.chunks_exact_mut(new_stride),
-        sample_map.chunks_exact(stride),
-    ) {
-        out_line.copy_from_slice(&in_line[0..new_stride]);
-    }
+    Ok(best.into_source(metadata))
+}
 
-    Ok(ThumbnailSource::VideoFrame(width, height, buf))
+/// Candidate video frame copied out of its sample
+struct ScoredFrame {
+    width: u32,
+    height: u32,
+    /// RGB data without padding
+    data: Vec<u8>,
+    timestamp: Option<gst::ClockTime>,
+    score: f32,
+    black: bool,
+    /// Only computed for [`ThumbnailerConfig::min_variance_threshold`]
+    variance: Option<f32>,
 }
 
-fn state_change_error_details(pipeline: &gst::Pipeline) -> String {
-    let mut err_msg = String::from("Error: Failed setting pipeline to PAUSED");
-    if let Some(msg) = pipeline
-        .bus()
-        .unwrap()
-        .pop_filtered(&[gst::MessageType::Error])
-    {
-        let gst::MessageView::Error(msg) = msg.view() else {
-            unreachable!();
+impl ScoredFrame {
+    /// Copy the frame into `buf` and score it
+    fn new(sample: &gst::Sample, config: &ThumbnailerConfig, mut buf: Vec<u8>) -> Result<Self> {
+        let caps = sample
+            .caps()
+            .ok_or_else(|| Error::other("Sample without caps"))?;
+        let info = gst_video::VideoInfo::from_caps(caps)?;
+        let width = info.width();
+        let height = info.height();
+        let stride = info.stride()[0] as usize;
+
+        let new_stride = width as usize * 3;
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| Error::other("Sample without buffer"))?;
+        let sample_map = buffer.map_readable()?;
+
+        // Get rid of padding after stride
+        buf.resize(height as usize * new_stride, 0);
+        for (out_line, in_line) in Iterator::zip(
+            buf.chunks_exact_mut(new_stride),
+            sample_map.chunks_exact(stride),
+        ) {
+            out_line.copy_from_slice(&in_line[0..new_stride]);
+        }
+
+        let frame = FrameRef {
+            data: &buf,
+            width,
+            height,
+            stride: new_stride as u32,
         };
+        let score = config.frame_selector.score(&frame);
+        let variance = config.min_variance_threshold.map(|_| variance(&frame));
+        let black = is_likely_black_frame(
+            &buf,
+            width,
+            new_stride as u32,
+            height,
+            config.black_frame_threshold,
+        );
 
-        err_msg.push('\n');
-        err_msg.push_str(&msg.to_string());
-        if let Some(debug) = msg.debug() {
-            err_msg.push('\n');
-            err_msg.push_str(&debug);
-        }
+        Ok(Self {
+            width,
+            height,
+            data: buf,
+            timestamp: buffer.pts(),
+            score,
+            black,
+            variance,
+        })
     }
 
-    err_msg
-}
-
-fn get_thumbnail_from_tag(tag: &gst::message::Tag) -> Option<gst::Sample> {
-    // Check for any cover art.
-    let tags = tag.tags();
-    let mut cover_sample = None;
-    for sample_value in tags.iter_tag::<gst::tags::Image>() {
-        let sample = sample_value.get();
-        let Some(caps) = sample.caps() else { continue };
+    /// The frame as thumbnail source with its position in `metadata`
+    fn into_source(self, metadata: ThumbMetadata) -> (ThumbnailSource, ThumbMetadata) {
+        let source = ThumbnailSource::VideoFrame {
+            width: self.width,
+            height: self.height,
+            data: self.data,
+            timestamp: self.timestamp,
+        };
 
-        let image_type = caps
-            .structure(0)
-            .and_then(|s| s.get::<i32>("image-type").ok());
+        (
+            source,
+            ThumbMetadata {
+                movie_offset: self.timestamp,
+                ..metadata
+            },
+        )
+    }
 
-        // TODO: Use gst_tag::TagImageType when it's properly exported
-        // Hardcoding values: 0 = None, 1 = Undefined, 3 = FrontCover
-        // See: https://gitlab.gnome.org/sophie-h/gst-thumbnailers/-/issues/4
-        match image_type {
-            Some(3) => {
-                // Front cover found - use it immediately
-                return Some(sample);
-            }
-            Some(1) | None if cover_sample.is_none() => {
-                // Save as fallback
-                cover_sample = Some(sample);
-            }
-            _ => {}
+    /// Black frames only win if all frames are black
+    fn is_better_than(&self, other: &Self) -> bool {
+        match (self.black, other.black) {
+            (false, true) => true,
+            (true, false) => false,
+            _ => self.score > other.score,
         }
     }
+}
 
-    cover_sample
+/// Seek to `position`
+///
+/// Failures are logged since a single failed seek isn't fatal.
+fn seek(pipeline: &gst::Pipeline, position: gst::ClockTime) -> Result<()> {
+    debug!("Seeking to {position}");
+
+    pipeline
+        .seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT, position)
+        .map_err(|err| {
+            warn!("Failed to seek to {position}");
+            Error::new(ErrorKind::SeekFailed {
+                position,
+                gst_error: err.to_string(),
+            })
+        })
 }
 
-fn scale_thumbnail_dimensions(width: f32, height: f32, thumbnail_size: u16) -> (u32, u32) {
-    let thumbnail_size = thumbnail_size as f32;
-    let scale = if width < thumbnail_size && height < thumbnail_size {
-        // avoid upscaling
-        1.0
-    } else if width > height {
-        thumbnail_size / width
-    } else {
-        thumbnail_size / height
+/// Whether the stream uses an HDR transfer function or BT.2020 primaries
+fn is_hdr(s: &gst::StructureRef) -> bool {
+    let Some(colorimetry) = s
+        .get::<&str>("colorimetry")
+        .ok()
+        .and_then(|colorimetry| colorimetry.parse::<gst_video::VideoColorimetry>().ok())
+    else {
+        return false;
     };
-    let new_width = (width * scale).round() as u32;
-    let new_height = (height * scale).round() as u32;
 
-    (new_width, new_height)
+    matches!(
+        colorimetry.transfer(),
+        gst_video::VideoTransferFunction::Smpte2084 | gst_video::VideoTransferFunction::AribStdB67
+    ) || colorimetry.primaries() == gst_video::VideoColorPrimaries::Bt2020
 }
 
-fn filter_hw_decoders(feature: &gst::PluginFeature) -> bool {
-    let factory = match feature.downcast_ref::<gst::ElementFactory>() {
-        Some(f) => f,
-        None => return false,
-    };
-    factory.has_type(gst::ElementFactoryType::MEDIA_VIDEO)
-        && factory.has_type(gst::ElementFactoryType::DECODER)
-        && factory.has_type(gst::ElementFactoryType::HARDWARE)
-}
+/// Tone-mapping element using the Hable curve if it offers a choice
+fn make_tonemap() -> Result<gst::Element> {
+    let tonemap = gst::ElementFactory::make("tonemap").build()?;
 
-fn disable_hardware_decoders() {
-    let registry = gst::Registry::get();
-    let hw_list = registry.features_filtered(filter_hw_decoders, false);
-    for l in hw_list.iter() {
-        registry.remove_feature(l);
+    if let Some(pspec) = tonemap.find_property("tone-map-mode")
+        && glib::EnumClass::with_type(pspec.value_type())
+            .is_some_and(|class| class.value_by_nick("hable").is_some())
+    {
+        tonemap.set_property_from_str("tone-map-mode", "hable");
     }
+
+    Ok(tonemap)
 }
 
-struct Pipeline(gst::Pipeline);
+/// Set the pipeline to `Paused` and wait until it prerolled
+///
+/// Every other bus message is passed to `handle_message`, which can end
+/// prerolling early by returning a value. If the state change fails or the
+/// pipeline posts an error before prerolling finished, it's retried as often
+/// as configured. `reset` is called before every retry.
+fn preroll<T>(
+    pipeline: &Pipeline,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+    mut handle_message: impl FnMut(&gst::Message) -> Result<Option<T>>,
+    mut reset: impl FnMut(),
+) -> Result<Option<T>> {
+    let mut attempt = 0;
+    loop {
+        match preroll_attempt(pipeline, config, cancellable, &mut handle_message) {
+            Err(PrerollError::Pipeline(err)) if attempt < config.preroll_retries => {
+                attempt += 1;
+                verbose!(
+                    "Prerolling failed, retrying ({attempt}/{}): {err}",
+                    config.preroll_retries
+                );
+                let _ = pipeline.set_state(gst::State::Null);
+                // Errors of the failed attempt would end the next one
+                while pipeline.bus().unwrap().pop().is_some() {}
 
-impl Pipeline {
-    pub fn new() -> Self {
-        Self(gst::Pipeline::new())
+                std::thread::sleep(config.preroll_retry_delay);
+                check_cancelled(cancellable)?;
+                reset();
+            }
+            Err(PrerollError::Pipeline(err) | PrerollError::Other(err)) => return Err(err),
+            Ok(value) => return Ok(value),
+        }
     }
 }
 
-impl std::ops::Deref for Pipeline {
-    type Target = gst::Pipeline;
+/// Failure of a single attempt in [`preroll`]
+enum PrerollError {
+    /// The pipeline failed, which might work when retried
+    Pipeline(Error),
+    Other(Error),
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl From<Error> for PrerollError {
+    fn from(err: Error) -> Self {
+        Self::Other(err)
     }
 }
 
-impl Drop for Pipeline {
-    fn drop(&mut self) {
-        let _ = self.0.set_state(gst::State::Null);
+/// A single attempt of [`preroll`] without retries
+fn preroll_attempt<T>(
+    pipeline: &Pipeline,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+    handle_message: &mut impl FnMut(&gst::Message) -> Result<Option<T>>,
+) -> std::result::Result<Option<T>, PrerollError> {
+    let state_change = pipeline
+        .set_state_with_timeout(
+            gst::State::Paused,
+            config.pipeline_timeout,
+            cancellable,
+            "preroll",
+        )
+        .map_err(|err| {
+            // Retrying would only wait for the retry delay
+            if cancellable.is_some_and(|cancellable| cancellable.is_cancelled()) {
+                PrerollError::Other(err)
+            } else {
+                PrerollError::Pipeline(err)
+            }
+        })?;
+    if state_change == gst::StateChangeSuccess::NoPreroll {
+        return Err(Error::other("Error: thumbnails of live streams make little sense").into());
+    }
+
+    // Wait until stream is initialized
+    while let Some(message) = pipeline.pop_message(config.pipeline_timeout, "preroll")? {
+        check_cancelled(cancellable)?;
+
+        match message.view() {
+            gst::MessageView::AsyncDone(_) => break,
+            gst::MessageView::Error(err) => {
+                return Err(PrerollError::Pipeline(Error::other(format!(
+                    "Failed pre-rolling pipeline: {err}"
+                ))));
+            }
+            _ => {
+                if let Some(value) = handle_message(&message)? {
+                    return Ok(Some(value));
+                }
+            }
+        }
     }
+
+    Ok(None)
 }
 
-#[derive(Debug)]
-pub enum ThumbnailSource {
-    VideoFrame(u32, u32, Vec<u8>),
-    CoverArt(gst::Sample),
+/// Fail if the highest variance of the candidate frames is below
+/// [`ThumbnailerConfig::min_variance_threshold`]
+fn check_not_blank(config: &ThumbnailerConfig, best_variance: Option<f32>) -> Result<()> {
+    if let (Some(threshold), Some(best_variance)) = (config.min_variance_threshold, best_variance)
+        && best_variance < threshold
+    {
+        return Err(Error::new(ErrorKind::BlankFrames { best_variance }));
+    }
+
+    Ok(())
 }
 
-impl ThumbnailSource {
-    fn write_png(&self, output_path: &Path, thumbnail_size: u16) -> Result<()> {
-        match self {
-            ThumbnailSource::VideoFrame(width, height, frame) => {
-                write_png(output_path, *width, *height, frame)?;
-                Ok(())
-            }
-            ThumbnailSource::CoverArt(sample) => {
-                let buffer = sample.buffer().unwrap();
-                let map = buffer.map_readable()?;
+/// Wait until the pipeline prerolled again after a seek
+fn wait_for_seek(pipeline: &Pipeline, config: &ThumbnailerConfig, operation: &str) -> Result<()> {
+    let msg = pipeline.pop_message_filtered(
+        &[gst::MessageType::Error, gst::MessageType::AsyncDone],
+        config.pipeline_timeout,
+        operation,
+    )?;
 
-                let loader = gly::Loader::for_bytes(&gly::glib::Bytes::from_owned(map.to_vec()));
-                loader.set_accepted_memory_formats(gly::MemoryFormatSelection::R8G8B8);
+    if let Some(gst::MessageView::Error(err)) = msg.as_ref().map(|msg| msg.view()) {
+        return Err(Error::other(format!(
+            "Error: Failed pre-rolling pipeline after seek: {err}"
+        )));
+    }
 
-                let image = loader.load()?;
-                let frame = image.next_frame()?;
+    Ok(())
+}
 
-                let (thumbnail_width, thumbnail_height) = scale_thumbnail_dimensions(
-                    frame.width() as f32,
-                    frame.height() as f32,
-                    thumbnail_size,
-                );
-                let data = resize::<image::Rgb<u8>>(&frame, thumbnail_width, thumbnail_height);
-
-                let creator = gly::Creator::new("image/png")?;
-                creator.add_frame(
-                    thumbnail_width,
-                    thumbnail_height,
-                    frame.memory_format(),
-                    &gly::glib::Bytes::from_owned(data),
-                )?;
+/// Dimensions of the decoded video as it enters the first filter
+fn source_dimensions(first_filter: &gst::Element) -> Option<(u32, u32)> {
+    let caps = first_filter.static_pad("sink")?.current_caps()?;
+    let info = gst_video::VideoInfo::from_caps(&caps).ok()?;
 
-                let image_data = creator.create()?.unwrap();
+    Some((info.width(), info.height()))
+}
 
-                std::fs::File::create(output_path)
-                    .unwrap()
-                    .write_all(&image_data.data())?;
+/// Start times of the chapters in the TOC that reached the appsink
+///
+/// Chapters of all editions are returned in order, nested chapters are
+/// ignored.
+fn chapter_start_times(appsink: &gst_app::AppSink) -> Vec<gst::ClockTime> {
+    let Some(event) = appsink
+        .static_pad("sink")
+        .and_then(|pad| pad.sticky_event::<gst::event::Toc>(0))
+    else {
+        return Vec::new();
+    };
+    let (toc, _) = event.toc();
+
+    let mut start_times = Vec::new();
+    for entry in toc.entries() {
+        // Matroska groups chapters in editions
+        let chapters = if entry.entry_type() == gst::TocEntryType::Edition {
+            entry.sub_entries()
+        } else {
+            vec![entry]
+        };
 
-                Ok(())
+        for chapter in chapters {
+            if chapter.entry_type() == gst::TocEntryType::Chapter
+                && let Some((start, _)) = chapter.start_stop_times()
+                && start >= 0
+            {
+                start_times.push(gst::ClockTime::from_nseconds(start as u64));
             }
         }
     }
+
+    start_times
 }
 
-fn write_png(
-    output_path: &Path,
-    thumbnail_width: u32,
-    thumbnail_height: u32,
-    buf: &[u8],
-) -> Result<()> {
-    let creator = gly::Creator::new("image/png")?;
+fn pull_preroll(appsink: &gst_app::AppSink) -> Result<gst::Sample> {
+    let sample = appsink.pull_preroll().map_err(|_| {
+        // Pulling only fails if the appsink is at EOS or flushing
+        if appsink.is_eos() {
+            Error::from(gst::FlowError::Eos)
+        } else {
+            Error::from(gst::FlowError::Flushing)
+        }
+    })?;
+
+    debug!(
+        "Prerolled {} bytes at {} with caps {}",
+        sample.buffer().map_or(0, |buffer| buffer.size()),
+        sample.buffer().and_then(|buffer| buffer.pts()).display(),
+        sample
+            .caps()
+            .map_or_else(|| "none".to_string(), |caps| caps.to_string()),
+    );
+
+    Ok(sample)
+}
+
+fn configure_decoder_threads(element: &gst::Element, threads: NonZeroU32) {
+    let Some(factory) = element.factory() else {
+        return;
+    };
+    let threads = threads.get();
+
+    match factory.name().as_str() {
+        // WARNING!
+        // Be careful adding support for new elements in the future here. Make sure
+        // your tests have covered newly added code, since it's easy to use an incorrect
+        // type for the "number of threads" property. Some elements use an unsigned
+        // integer, others a signed integer. Mixing them up will result
+        // in a runtime crash with no compiler warning.
+        factory_name if factory_name.starts_with("avdec_") => {
+            let gobject_class = element.class();
+            if gobject_class.find_property("max-threads").is_some() {
+                element.set_property("max-threads", threads as i32);
+            }
+        }
+        "dav1ddec" => {
+            element.set_property("n-threads", threads);
+        }
+        "vp8dec" | "vp9dec" => {
+            element.set_property("threads", threads);
+        }
+        "openh264dec" => {
+            // WARNING: "num-threads" is unsigned
+            element.set_property("num-threads", threads);
+        }
+        "x265dec" => {
+            // WARNING: "frame-threads" is signed
+            element.set_property("frame-threads", threads as i32);
+        }
+        "msdkh265dec" => {
+            // WARNING: "num-thread" is unsigned and singular
+            element.set_property("num-thread", threads);
+        }
+        "rav1edec" => {
+            // No threads property, keep as few frames in flight as possible
+            // instead to limit memory usage
+            // WARNING: "max-frame-delay" is unsigned
+            element.set_property("max-frame-delay", 0u32);
+            element.set_property("low-latency", true);
+        }
+        "av1dec" => {
+            // WARNING: "n-threads" is unsigned
+            element.set_property("n-threads", threads);
+        }
+        _ => (),
+    }
+}
+
+/// ID of the stream to select instead of the default one
+fn select_video_stream(
+    selector: VideoStreamSelector,
+    collection: &gst::StreamCollection,
+) -> Option<glib::GString> {
+    let mut video_streams = collection
+        .iter()
+        .filter(|stream| stream.stream_type().contains(gst::StreamType::VIDEO));
+
+    let stream = match selector {
+        VideoStreamSelector::First => None,
+        VideoStreamSelector::HighestResolution => video_streams.max_by_key(|stream| {
+            stream
+                .caps()
+                .and_then(|caps| {
+                    let s = caps.structure(0)?;
+                    let width = s.get::<i32>("width").ok()?;
+                    let height = s.get::<i32>("height").ok()?;
+                    Some(width as i64 * height as i64)
+                })
+                .unwrap_or_default()
+        }),
+        VideoStreamSelector::StreamIndex(index) => video_streams.nth(index),
+    };
+
+    stream?.stream_id()
+}
+
+fn seek_percentages(duration: gst::ClockTime, num_positions: NonZeroU8) -> Vec<u64> {
+    let default_positions = if duration > 180.seconds() {
+        // For long videos, take frames at 10%, 15%, 20%, 25%, 30% of the
+        // video This only uses the first third of the video to not spoiler
+        // films
+        [10, 15, 20, 25, 30]
+    } else {
+        // For short videos, sample from the complete video
+        [10, 20, 30, 60, 90]
+    };
+
+    let n = num_positions.get() as usize;
+    if n <= default_positions.len() {
+        return default_positions[..n].to_vec();
+    }
+
+    // Distribute positions evenly over the same range
+    let first = default_positions[0];
+    let last = default_positions[default_positions.len() - 1];
+    let n = n as u64;
+    (0..n)
+        .map(|i| first + (last - first) * i / (n - 1))
+        .collect()
+}
+
+fn check_cancelled(cancellable: Option<&gio::Cancellable>) -> Result<()> {
+    if cancellable.is_some_and(|cancellable| cancellable.is_cancelled()) {
+        Err(Error::other("Cancelled"))
+    } else {
+        Ok(())
+    }
+}
+
+/// The error message that caused a state change to fail
+#[track_caller]
+fn state_change_error_details(pipeline: &gst::Pipeline) -> Error {
+    let Some(msg) = pipeline
+        .bus()
+        .unwrap()
+        .pop_filtered(&[gst::MessageType::Error])
+    else {
+        return Error::other("Failed setting pipeline to PAUSED");
+    };
+
+    let gst::MessageView::Error(msg) = msg.view() else {
+        unreachable!();
+    };
+
+    Error::gst_message(msg)
+}
+
+fn get_thumbnail_from_tag(tag: &gst::message::Tag) -> Option<gst::Sample> {
+    get_all_thumbnail_candidates(tag)
+        .into_iter()
+        .find(|(image_type, _)| {
+            matches!(
+                image_type,
+                TagImageType::FrontCover | TagImageType::None | TagImageType::Undefined
+            )
+        })
+        .map(|(_, sample)| sample)
+}
+
+/// All images in `tag`, the most suitable thumbnail first
+///
+/// Front covers come first, followed by images without a specific type.
+/// Images with an unknown type are skipped. Preview images are only used if
+/// there are no other images, since some formats like APE and WavPack only
+/// store those.
+pub fn get_all_thumbnail_candidates(tag: &gst::message::Tag) -> Vec<(TagImageType, gst::Sample)> {
+    let tags = tag.tags();
+    let with_image_type = |sample: gst::Sample| {
+        let image_type = TagImageType::from_caps(sample.caps()?).ok()?;
+        Some((image_type, sample))
+    };
+
+    let mut candidates = tags
+        .iter_tag::<gst::tags::Image>()
+        .filter_map(|sample_value| with_image_type(sample_value.get()))
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        candidates = tags
+            .iter_tag::<gst::tags::PreviewImage>()
+            .filter_map(|sample_value| with_image_type(sample_value.get()))
+            .collect();
+    }
+
+    // Stable sort keeps the order of the file for images of the same type
+    candidates.sort_by_key(|(image_type, _)| image_type.priority());
+
+    candidates
+}
+
+/// The first candidate that can be decoded and is large enough
+///
+/// Falls back to the largest image if all are smaller than
+/// [`ThumbnailerConfig::min_cover_resolution`].
+fn select_cover_art(
+    candidates: Vec<(TagImageType, gst::Sample)>,
+    config: &ThumbnailerConfig,
+) -> Option<gst::Sample> {
+    let mut largest: Option<(u64, gst::Sample)> = None;
+
+    for (_, sample) in candidates {
+        let Some((width, height)) = decoded_cover_art_dimensions(&sample) else {
+            continue;
+        };
+        let Some((min_width, min_height)) = config.min_cover_resolution else {
+            return Some(sample);
+        };
+        if width >= min_width && height >= min_height {
+            return Some(sample);
+        }
+
+        let pixels = width as u64 * height as u64;
+        if largest.as_ref().is_none_or(|(max, _)| pixels > *max) {
+            largest = Some((pixels, sample));
+        }
+    }
+
+    largest.map(|(_, sample)| sample)
+}
+
+fn filter_hw_decoders(feature: &gst::PluginFeature) -> bool {
+    let factory = match feature.downcast_ref::<gst::ElementFactory>() {
+        Some(f) => f,
+        None => return false,
+    };
+    factory.has_type(gst::ElementFactoryType::MEDIA_VIDEO)
+        && factory.has_type(gst::ElementFactoryType::DECODER)
+        && factory.has_type(gst::ElementFactoryType::HARDWARE)
+}
+
+/// Decoder options of a [`ThumbnailerConfig`]
+#[derive(Clone, PartialEq)]
+struct DecoderSettings {
+    allow_hardware: bool,
+    allowlist: Option<Vec<String>>,
+    denylist: Vec<String>,
+}
+
+impl From<&ThumbnailerConfig> for DecoderSettings {
+    fn from(config: &ThumbnailerConfig) -> Self {
+        Self {
+            allow_hardware: config.allow_hardware_decoders,
+            allowlist: config.decoder_allowlist.clone(),
+            denylist: config.decoder_denylist.clone(),
+        }
+    }
+}
+
+/// Decoders removed from the process-wide registry
+///
+/// This could be solved in a cleaner way when GStreamer adds support for
+/// sorting decoder factories in uridecodebin3.
+/// See: https://gitlab.freedesktop.org/gstreamer/gstreamer/-/issues/959
+/// and  https://gitlab.freedesktop.org/gstreamer/gstreamer/-/merge_requests/9672
+struct DecoderRegistry {
+    /// Settings the registry was last changed for
+    applied: Option<DecoderSettings>,
+    /// The registry has no way to look up removed features, so they are
+    /// kept here to be able to add them back
+    removed: Vec<gst::PluginFeature>,
+}
+
+/// Pipelines hold a read lock until they created their decoders, so
+/// pipelines with different decoder settings don't preroll at the same time
+static DECODER_REGISTRY: RwLock<DecoderRegistry> = RwLock::new(DecoderRegistry {
+    applied: None,
+    removed: Vec::new(),
+});
+
+impl DecoderRegistry {
+    /// Registry with the decoders allowed by `config`, to be kept until the
+    /// pipeline prerolled
+    fn for_config(config: &ThumbnailerConfig) -> RwLockReadGuard<'static, Self> {
+        let settings = DecoderSettings::from(config);
+        loop {
+            let registry = DECODER_REGISTRY.read().unwrap();
+            if registry.applied.as_ref() == Some(&settings) {
+                return registry;
+            }
+            drop(registry);
+
+            let mut registry = DECODER_REGISTRY.write().unwrap();
+            if registry.applied.as_ref() != Some(&settings) {
+                registry.apply(settings.clone());
+            }
+        }
+    }
+
+    /// Undo the previous changes to the registry and remove the decoders
+    /// `settings` don't allow
+    fn apply(&mut self, settings: DecoderSettings) {
+        let registry = gst::Registry::get();
+        for feature in self.removed.drain(..) {
+            if registry.lookup_feature(&feature.name()).is_none()
+                && registry.add_feature(&feature).is_err()
+            {
+                warn!("Failed to re-enable decoder {}", feature.name());
+            }
+        }
+
+        let mut disabled = Vec::new();
+        if !settings.allow_hardware {
+            let allowlist = settings.allowlist.as_deref().unwrap_or_default();
+            for feature in registry.features_filtered(filter_hw_decoders, false) {
+                if !allowlist.iter().any(|name| feature.name().as_str() == name) {
+                    disabled.push(feature);
+                }
+            }
+        }
+        // Unknown names are ignored
+        disabled.extend(
+            settings
+                .denylist
+                .iter()
+                .filter_map(|name| registry.lookup_feature(name)),
+        );
+
+        for feature in disabled {
+            if !self.removed.contains(&feature) {
+                registry.remove_feature(&feature);
+                self.removed.push(feature);
+            }
+        }
+        self.applied = Some(settings);
+    }
+}
+
+struct Pipeline(gst::Pipeline);
+
+impl Pipeline {
+    /// The name makes debug output like dot files attributable
+    pub fn new(name: &str) -> Self {
+        Self(gst::Pipeline::with_name(name))
+    }
+
+    /// Change the state and wait until asynchronous state changes complete
+    ///
+    /// Some broken elements never finish their state change. Without a
+    /// timeout this returns immediately like `set_state`, so asynchronous
+    /// state changes have to be tracked via the bus.
+    ///
+    /// The bus is polled so that cancelling doesn't wait for the timeout. The
+    /// `AsyncDone` message is left on the bus for callers waiting for it.
+    pub fn set_state_with_timeout(
+        &self,
+        state: gst::State,
+        timeout: Option<Duration>,
+        cancellable: Option<&gio::Cancellable>,
+        operation: &str,
+    ) -> Result<gst::StateChangeSuccess> {
+        let start = Instant::now();
+        let result = self
+            .set_state(state)
+            .map_err(|_| state_change_error_details(self))?;
+        let Some(timeout) = timeout.filter(|_| result == gst::StateChangeSuccess::Async) else {
+            return Ok(result);
+        };
+
+        let bus = self.bus().unwrap();
+        let types = [gst::MessageType::AsyncDone, gst::MessageType::Error];
+        loop {
+            check_cancelled(cancellable)?;
+
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                let _ = self.set_state(gst::State::Null);
+                return Err(Error::new(ErrorKind::Timeout {
+                    elapsed: start.elapsed(),
+                    operation: operation.to_string(),
+                }));
+            }
+
+            let poll_interval = remaining.min(STATE_CHANGE_POLL_INTERVAL);
+            let Some(message) = bus.timed_pop_filtered(clock_time(Some(poll_interval)), &types)
+            else {
+                continue;
+            };
+            match message.view() {
+                gst::MessageView::Error(err) => return Err(Error::gst_message(err)),
+                _ => {
+                    let _ = bus.post(message);
+                    return Ok(gst::StateChangeSuccess::Success);
+                }
+            }
+        }
+    }
+
+    /// Write the pipeline graph to a timestamped dot file in
+    /// `GST_DEBUG_DUMP_DOT_DIR` if that is set
+    pub fn dump_dot_if_enabled(&self, name: &str) {
+        if std::env::var_os("GST_DEBUG_DUMP_DOT_DIR").is_some() {
+            self.debug_to_dot_file_with_ts(gst::DebugGraphDetails::all(), name);
+        }
+    }
+
+    /// Wait for the next bus message
+    ///
+    /// Without a timeout this blocks until a message arrives. `operation`
+    /// describes what is waited for in the timeout error.
+    pub fn pop_message(
+        &self,
+        timeout: Option<Duration>,
+        operation: &str,
+    ) -> Result<Option<gst::Message>> {
+        let start = Instant::now();
+        let message = self.bus().unwrap().timed_pop(clock_time(timeout));
+        self.check_timeout(message, timeout, start, operation)
+    }
+
+    /// Wait for the next bus message of one of the given types
+    pub fn pop_message_filtered(
+        &self,
+        types: &[gst::MessageType],
+        timeout: Option<Duration>,
+        operation: &str,
+    ) -> Result<Option<gst::Message>> {
+        let start = Instant::now();
+        let message = self
+            .bus()
+            .unwrap()
+            .timed_pop_filtered(clock_time(timeout), types);
+        self.check_timeout(message, timeout, start, operation)
+    }
+
+    fn check_timeout(
+        &self,
+        message: Option<gst::Message>,
+        timeout: Option<Duration>,
+        start: Instant,
+        operation: &str,
+    ) -> Result<Option<gst::Message>> {
+        match (message, timeout) {
+            (None, Some(_)) => {
+                let _ = self.set_state(gst::State::Null);
+                Err(Error::new(ErrorKind::Timeout {
+                    elapsed: start.elapsed(),
+                    operation: operation.to_string(),
+                }))
+            }
+            (message, _) => Ok(message),
+        }
+    }
+}
+
+/// How often [`Pipeline::set_state_with_timeout`] checks for cancellation
+const STATE_CHANGE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn clock_time(timeout: Option<Duration>) -> Option<gst::ClockTime> {
+    timeout.map(|timeout| gst::ClockTime::from_nseconds(timeout.as_nanos() as u64))
+}
+
+impl std::ops::Deref for Pipeline {
+    type Target = gst::Pipeline;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        let _ = self.0.set_state(gst::State::Null);
+    }
+}
+
+#[derive(Debug)]
+pub enum ThumbnailSource {
+    /// Packed RGB image
+    VideoFrame {
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        /// Position of the frame in the video if known
+        timestamp: Option<gst::ClockTime>,
+    },
+    CoverArt(gst::Sample),
+}
+
+/// Summary for log messages like `VideoFrame(320×240, 230400 bytes, ts=3s500ms)`
+///
+/// Cover art isn't decoded for this, so only its type and size are shown.
+impl std::fmt::Display for ThumbnailSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThumbnailSource::VideoFrame {
+                width,
+                height,
+                data,
+                timestamp,
+            } => {
+                write!(f, "VideoFrame({width}×{height}, {} bytes", data.len())?;
+                if let Some(timestamp) = timestamp {
+                    let millis = timestamp.mseconds();
+                    write!(f, ", ts={}s{}ms", millis / 1000, millis % 1000)?;
+                }
+                f.write_str(")")
+            }
+            ThumbnailSource::CoverArt(sample) => {
+                let mime_type = sample
+                    .caps()
+                    .and_then(|caps| caps.structure(0))
+                    .map_or("unknown", |s| s.name().as_str());
+                let size = sample.buffer().map_or(0, |buffer| buffer.size());
+                write!(f, "CoverArt({mime_type}, {size} bytes)")
+            }
+        }
+    }
+}
+
+impl ThumbnailSource {
+    /// Width and height of the extracted image before scaling
+    ///
+    /// Cover art has to be decoded for this, which fails for broken images.
+    pub fn dimensions(&self) -> Result<(u32, u32)> {
+        match self {
+            ThumbnailSource::VideoFrame { width, height, .. } => Ok((*width, *height)),
+            ThumbnailSource::CoverArt(sample) => {
+                load_cover_art(sample, true).map(|image| (image.width(), image.height()))
+            }
+        }
+    }
+
+    pub fn is_cover_art(&self) -> bool {
+        matches!(self, ThumbnailSource::CoverArt(_))
+    }
+
+    pub fn is_video_frame(&self) -> bool {
+        matches!(self, ThumbnailSource::VideoFrame { .. })
+    }
+
+    /// The embedded image if this is cover art
+    pub fn as_cover_art(&self) -> Option<&gst::Sample> {
+        match self {
+            ThumbnailSource::CoverArt(sample) => Some(sample),
+            ThumbnailSource::VideoFrame { .. } => None,
+        }
+    }
+
+    /// Width, height, and RGB data if this is a video frame
+    pub fn as_video_frame(&self) -> Option<(u32, u32, &[u8])> {
+        match self {
+            ThumbnailSource::VideoFrame {
+                width,
+                height,
+                data,
+                ..
+            } => Some((*width, *height, data)),
+            ThumbnailSource::CoverArt(_) => None,
+        }
+    }
+
+    /// Width and height of the written thumbnail
+    fn write_image(
+        &self,
+        output_path: &Path,
+        config: &ThumbnailerConfig,
+        metadata: &ThumbMetadata,
+    ) -> Result<(u32, u32)> {
+        let (width, height, data) = self.encode(config, metadata)?;
+        write_output(output_path, &data, config)?;
+
+        Ok((width, height))
+    }
+
+    /// Like [`Self::write_image`] with the path from [`expand_template`]
+    fn write_image_to_template(
+        &self,
+        template: &str,
+        input_uri: &str,
+        config: &ThumbnailerConfig,
+        metadata: &ThumbMetadata,
+    ) -> Result<()> {
+        let (width, height, data) = self.encode(config, metadata)?;
+        let output_path = expand_template(template, input_uri, (width, height))?;
+
+        write_output(&output_path, &data, config)
+    }
+
+    /// Scale and encode the image according to `config`
+    ///
+    /// This is what ends up in the output file when thumbnailing to a path.
+    pub fn encode_to_bytes(&self, config: &ThumbnailerConfig) -> Result<Vec<u8>> {
+        self.encode_to_bytes_with_metadata(config, &ThumbMetadata::default())
+    }
+
+    /// Like [`Self::encode_to_bytes`] but stores `metadata` in PNG output
+    pub fn encode_to_bytes_with_metadata(
+        &self,
+        config: &ThumbnailerConfig,
+        metadata: &ThumbMetadata,
+    ) -> Result<Vec<u8>> {
+        let (_, _, data) = self.encode(config, metadata)?;
+        Ok(data)
+    }
+
+    /// Encoded image with its width and height
+    fn encode(
+        &self,
+        config: &ThumbnailerConfig,
+        metadata: &ThumbMetadata,
+    ) -> Result<(u32, u32, Vec<u8>)> {
+        match self {
+            ThumbnailSource::VideoFrame {
+                width,
+                height,
+                data,
+                ..
+            } => {
+                let (width, height, data) =
+                    fit_to_size_mode(*width, *height, data.clone(), config.size_mode, image::Rgb);
+                let data = encode_image(
+                    width,
+                    height,
+                    gly::MemoryFormat::R8g8b8,
+                    data,
+                    config,
+                    metadata,
+                )?;
+                Ok((width, height, data))
+            }
+            ThumbnailSource::CoverArt(sample) => {
+                let image = load_cover_art(sample, config.output_format.supports_alpha())?;
+                let frame = image.next_frame()?;
+
+                let (thumbnail_width, thumbnail_height) = config
+                    .size_mode
+                    .scaled_dimensions(frame.width() as f32, frame.height() as f32);
+
+                let (width, height, data) = if frame.memory_format() == gly::MemoryFormat::R8g8b8a8
+                {
+                    let data = resize::<image::Rgba<u8>>(
+                        &frame,
+                        thumbnail_width,
+                        thumbnail_height,
+                        &config.scale_filters,
+                        config.intermediate_scale_factor,
+                    );
+                    fit_to_size_mode(
+                        thumbnail_width,
+                        thumbnail_height,
+                        data,
+                        config.size_mode,
+                        |[r, g, b]| image::Rgba([r, g, b, 255]),
+                    )
+                } else {
+                    let data = resize::<image::Rgb<u8>>(
+                        &frame,
+                        thumbnail_width,
+                        thumbnail_height,
+                        &config.scale_filters,
+                        config.intermediate_scale_factor,
+                    );
+                    fit_to_size_mode(
+                        thumbnail_width,
+                        thumbnail_height,
+                        data,
+                        config.size_mode,
+                        image::Rgb,
+                    )
+                };
+
+                let data =
+                    encode_image(width, height, frame.memory_format(), data, config, metadata)?;
+                Ok((width, height, data))
+            }
+        }
+    }
+}
+
+fn write_output(output_path: &Path, data: &[u8], config: &ThumbnailerConfig) -> Result<()> {
+    if config.dry_run {
+        return Ok(());
+    }
+
+    if config.create_output_dir {
+        create_output_dir(output_path)?;
+    }
+
+    write_atomically(output_path, data)
+}
+
+fn create_output_dir(output_path: &Path) -> Result<()> {
+    let Some(dir) = output_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    else {
+        return Ok(());
+    };
+
+    std::fs::create_dir_all(dir).map_err(|err| Error::output_dir(dir, err))
+}
+
+fn load_cover_art(sample: &gst::Sample, with_alpha: bool) -> Result<gly::Image> {
+    let buffer = sample
+        .buffer()
+        .ok_or_else(|| Error::other("Cover art without data"))?;
+    let map = buffer.map_readable()?;
+
+    let loader = gly::Loader::for_bytes(&gly::glib::Bytes::from_owned(map.to_vec()));
+    // Keep the alpha channel for images with transparency
+    loader.set_accepted_memory_formats(if with_alpha {
+        gly::MemoryFormatSelection::R8G8B8 | gly::MemoryFormatSelection::R8G8B8A8
+    } else {
+        gly::MemoryFormatSelection::R8G8B8
+    });
+
+    Ok(loader.load()?)
+}
+
+/// Dimensions of the cover art if it can be decoded
+fn decoded_cover_art_dimensions(sample: &gst::Sample) -> Option<(u32, u32)> {
+    let image = load_cover_art(sample, true).ok()?;
+    image.next_frame().ok()?;
+
+    Some((image.width(), image.height()))
+}
+
+fn encode_image(
+    thumbnail_width: u32,
+    thumbnail_height: u32,
+    memory_format: gly::MemoryFormat,
+    buf: Vec<u8>,
+    config: &ThumbnailerConfig,
+    metadata: &ThumbMetadata,
+) -> Result<Vec<u8>> {
+    let creator = gly::Creator::new(config.output_format.mime_type())?;
+    if config.output_format == OutputFormat::Jpeg {
+        if !(1..=100).contains(&config.jpeg_quality) {
+            return Err(Error::other(format!(
+                "JPEG quality {} is not between 1 and 100",
+                config.jpeg_quality
+            )));
+        }
+        creator.set_encoding_quality(config.jpeg_quality);
+    }
+    if config.output_format == OutputFormat::Png
+        && let Some(compression) = config.png_compression.level()
+    {
+        creator.set_encoding_compression(compression);
+    }
+    if config.output_format == OutputFormat::Png {
+        for (key, value) in metadata.text_chunks() {
+            creator.add_metadata_key_value(key, &value);
+        }
+    }
+
     creator.add_frame(
         thumbnail_width,
         thumbnail_height,
-        gly::MemoryFormat::R8g8b8,
-        &gly::glib::Bytes::from_owned(buf.to_vec()),
+        memory_format,
+        &gly::glib::Bytes::from_owned(buf),
     )?;
 
     let encoded_image = creator.create()?.unwrap();
 
-    let data = encoded_image.data();
+    Ok(encoded_image.data().to_vec())
+}
 
-    let mut out_file = std::fs::File::create(output_path)?;
-    out_file.write_all(&data)?;
+/// Write to a temporary file next to `output_path` and move it into place
+///
+/// This way a thumbnailer that gets killed never leaves a truncated image
+/// behind that would be picked up as a valid thumbnail.
+fn write_atomically(output_path: &Path, mut data: impl std::io::Read) -> Result<()> {
+    // Special files like `/dev/null` must not be replaced
+    if output_path
+        .metadata()
+        .is_ok_and(|metadata| !metadata.is_file())
+    {
+        std::io::copy(&mut data, &mut std::fs::File::create(output_path)?)?;
+        return Ok(());
+    }
 
-    Ok(())
+    let dir = match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut prefix = output_path.file_name().unwrap_or_default().to_os_string();
+    prefix.push(".");
+
+    let mut tmp_file = tempfile::Builder::new()
+        .prefix(&prefix)
+        .suffix(".tmp")
+        // Temporary files are only readable by the owner by default
+        .permissions(std::fs::Permissions::from_mode(0o644))
+        .tempfile_in(dir)?;
+    // Dropping the temporary file on errors deletes it
+    std::io::copy(&mut data, &mut tmp_file)?;
+
+    match tmp_file.persist(output_path) {
+        Ok(_) => Ok(()),
+        // Happens if something is mounted at the output path itself
+        Err(err) if err.error.kind() == std::io::ErrorKind::CrossesDevices => {
+            std::fs::copy(err.file.path(), output_path)?;
+            // Dropping the temporary file deletes it
+            Ok(())
+        }
+        Err(err) => Err(err.error.into()),
+    }
+}
+
+/// Pad or crop the scaled image to the final thumbnail size
+///
+/// Returns the new dimensions and data.
+fn fit_to_size_mode<T: image::Pixel<Subpixel = u8> + 'static>(
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    size_mode: SizeMode,
+    background: impl FnOnce([u8; 3]) -> T,
+) -> (u32, u32, Vec<u8>) {
+    match size_mode {
+        SizeMode::Fit { .. } => (width, height, data),
+        SizeMode::Exact {
+            width: target_width,
+            height: target_height,
+            background: background_color,
+        } => letterbox(
+            width,
+            height,
+            data,
+            target_width as u32,
+            target_height as u32,
+            background(background_color),
+        ),
+        SizeMode::CropFill { size } => crop_center::<T>(width, height, data, size as u32),
+    }
+}
+
+/// Center the image on a `target_width` × `target_height` background
+fn letterbox<T: image::Pixel<Subpixel = u8>>(
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    target_width: u32,
+    target_height: u32,
+    background: T,
+) -> (u32, u32, Vec<u8>) {
+    let content = image::ImageBuffer::<T, _>::from_raw(width, height, data).unwrap();
+    let mut canvas = image::ImageBuffer::from_pixel(target_width, target_height, background);
+
+    image::imageops::overlay(
+        &mut canvas,
+        &content,
+        (target_width as i64 - width as i64) / 2,
+        (target_height as i64 - height as i64) / 2,
+    );
+
+    (target_width, target_height, canvas.into_raw())
+}
+
+/// Cut a `size` × `size` square out of the center of the image
+fn crop_center<T: image::Pixel<Subpixel = u8> + 'static>(
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    size: u32,
+) -> (u32, u32, Vec<u8>) {
+    let (crop_width, crop_height) = (size.min(width), size.min(height));
+
+    let content = image::ImageBuffer::<T, _>::from_raw(width, height, data).unwrap();
+    let cropped = image::imageops::crop_imm(
+        &content,
+        (width - crop_width) / 2,
+        (height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    )
+    .to_image();
+
+    (crop_width, crop_height, cropped.into_raw())
 }
 
 fn resize<T: image::Pixel<Subpixel = u8> + 'static>(
     frame: &gly::Frame,
     thumbnail_width: u32,
     thumbnail_height: u32,
+    filters: &ScaleFilterPair,
+    intermediate_scale_factor: f32,
 ) -> Vec<u8> {
     let img =
         image::ImageBuffer::<T, _>::from_raw(frame.width(), frame.height(), frame.buf_bytes())
             .unwrap();
 
+    let intermediate = |size: u32| (size as f32 * intermediate_scale_factor).round() as u32;
     let rought_scaled = image::imageops::resize(
         &img,
-        thumbnail_width * 2,
-        thumbnail_height * 2,
-        SCALE_FILTER1,
+        intermediate(thumbnail_width),
+        intermediate(thumbnail_height),
+        filters.coarse.into(),
     );
 
     image::imageops::resize(
         &rought_scaled,
         thumbnail_width,
         thumbnail_height,
-        SCALE_FILTER2,
+        filters.fine.into(),
     )
     .into_raw()
 }
 
-pub fn variance(xs: &[u8], width: u32, stride: u32, height: u32) -> f32 {
+pub fn variance(frame: &FrameRef<'_>) -> f32 {
+    let FrameRef {
+        data,
+        width,
+        height,
+        stride,
+    } = *frame;
+
+    #[cfg(feature = "simd")]
+    {
+        variance_simd(data, width, stride, height)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        variance_scalar(data, width, stride, height)
+    }
+}
+
+pub fn variance_scalar(xs: &[u8], width: u32, stride: u32, height: u32) -> f32 {
     let effective_stride = width as usize * 3; // format == "RGB"
     let len = (effective_stride * height as usize) as f32;
 
@@ -642,3 +2037,573 @@ pub fn variance(xs: &[u8], width: u32, stride: u32, height: u32) -> f32 {
 
     sq_diff / len
 }
+
+/// Variance over all RGB components of each cell in a `grid_rows` ×
+/// `grid_cols` grid, row by row
+///
+/// Cells at the right and bottom edge are larger if the frame doesn't
+/// divide evenly. Cells without pixels have a variance of 0.
+pub fn regional_variance(frame: &FrameRef<'_>, grid_rows: u8, grid_cols: u8) -> Vec<f32> {
+    let (rows, cols) = (grid_rows as usize, grid_cols as usize);
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let stride = frame.stride as usize;
+
+    let mut cells = Vec::with_capacity(rows * cols);
+    for row in 0..rows {
+        let lines = row * height / rows..(row + 1) * height / rows;
+        for col in 0..cols {
+            // format == "RGB"
+            let bytes = col * width / cols * 3..(col + 1) * width / cols * 3;
+            let len = (lines.len() * bytes.len()) as f32;
+            if len == 0. {
+                cells.push(0.);
+                continue;
+            }
+
+            let cell = || {
+                lines
+                    .clone()
+                    .flat_map(|y| &frame.data[y * stride..][bytes.clone()])
+                    .map(|&x| x as f32)
+            };
+            let avg = cell().sum::<f32>() / len;
+            let sq_diff = cell().map(|x| (x - avg).powi(2)).sum::<f32>();
+
+            cells.push(sq_diff / len);
+        }
+    }
+
+    cells
+}
+
+pub fn luminance_variance(xs: &[u8], width: u32, stride: u32, height: u32) -> f32 {
+    let effective_stride = width as usize * 3; // format == "RGB"
+    let len = (width as usize * height as usize) as f32;
+
+    let avg = xs
+        .chunks_exact(stride as usize)
+        .map(|line| {
+            line[0..effective_stride]
+                .chunks_exact(3)
+                .map(luminance)
+                .sum::<f32>()
+        })
+        .sum::<f32>()
+        / len;
+
+    let sq_diff = xs
+        .chunks_exact(stride as usize)
+        .map(|line| {
+            line[0..effective_stride]
+                .chunks_exact(3)
+                .map(|pixel| (luminance(pixel) - avg).powi(2))
+                .sum::<f32>()
+        })
+        .sum::<f32>();
+
+    sq_diff / len
+}
+
+/// Whether the mean luminance of the frame is below `threshold`
+pub fn is_likely_black_frame(
+    xs: &[u8],
+    width: u32,
+    stride: u32,
+    height: u32,
+    threshold: u8,
+) -> bool {
+    let effective_stride = width as usize * 3; // format == "RGB"
+    let len = (width as usize * height as usize) as f32;
+
+    let avg = xs
+        .chunks_exact(stride as usize)
+        .map(|line| {
+            line[0..effective_stride]
+                .chunks_exact(3)
+                .map(luminance)
+                .sum::<f32>()
+        })
+        .sum::<f32>()
+        / len;
+
+    avg < threshold as f32
+}
+
+/// Mean squared Sobel gradient of the luminance per pixel
+///
+/// The outermost pixels are skipped, frames smaller than 3×3 have no edge
+/// energy. The last row doesn't need to be padded to `stride`.
+pub fn edge_energy(xs: &[u8], width: u32, stride: u32, height: u32) -> f32 {
+    let (width, stride, height) = (width as usize, stride as usize, height as usize);
+    if width < 3 || height < 3 {
+        return 0.;
+    }
+
+    let luma = (0..height)
+        .flat_map(|y| {
+            let line = &xs[y * stride..y * stride + width * 3];
+            line.chunks_exact(3).map(luminance)
+        })
+        .collect::<Vec<_>>();
+    let at = |x: usize, y: usize| luma[y * width + x];
+
+    // Sobel operator, skipping the outermost pixels
+    let mut energy = 0.;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let gx = at(x + 1, y - 1) + 2. * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2. * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2. * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2. * at(x, y - 1)
+                - at(x + 1, y - 1);
+
+            energy += gx * gx + gy * gy;
+        }
+    }
+
+    energy / ((width - 2) * (height - 2)) as f32
+}
+
+/// BT.709 luminance of an RGB pixel
+fn luminance(pixel: &[u8]) -> f32 {
+    0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(data: &[u8], width: u32, stride: u32, height: u32) -> FrameRef<'_> {
+        FrameRef {
+            data,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    #[test]
+    fn test_pipeline_timeout_error() {
+        gst::init().unwrap();
+
+        // Nothing is ever posted on the bus of an empty pipeline
+        let pipeline = Pipeline::new("test-timeout");
+        let err = pipeline
+            .pop_message(Some(Duration::from_millis(50)), "preroll")
+            .unwrap_err();
+
+        let ErrorKind::Timeout { elapsed, operation } = err.kind() else {
+            panic!("Unexpected error {err}");
+        };
+        assert!(*elapsed >= Duration::from_millis(50));
+        assert_eq!(operation, "preroll");
+        let message = err.kind().to_string();
+        assert!(message.starts_with("Timed out after "));
+        assert!(message.ends_with(" during preroll"));
+    }
+
+    #[test]
+    fn test_set_state_cancelled() {
+        gst::init().unwrap();
+
+        // A sink without data never finishes prerolling
+        let pipeline = Pipeline::new("test-cancelled");
+        let sink = gst::ElementFactory::make("fakesink").build().unwrap();
+        pipeline.add(&sink).unwrap();
+
+        let cancellable = gio::Cancellable::new();
+        std::thread::spawn({
+            let cancellable = cancellable.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                cancellable.cancel();
+            }
+        });
+
+        let start = Instant::now();
+        let err = pipeline
+            .set_state_with_timeout(
+                gst::State::Paused,
+                Some(Duration::from_secs(30)),
+                Some(&cancellable),
+                "preroll",
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Cancelled"));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    /// Returns some data, then fails like a broken pipe
+    struct FailingReader(&'static [u8]);
+
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::other("read failed"));
+            }
+            std::io::Read::read(&mut self.0, buf)
+        }
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("thumbnail.png");
+        let files = || {
+            std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>()
+        };
+
+        std::fs::write(&path, b"old").unwrap();
+        write_atomically(&path, &b"new"[..]).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(files(), ["thumbnail.png"]);
+
+        // Failing part-way keeps the previous file and no temporary file
+        write_atomically(&path, FailingReader(b"partial")).unwrap_err();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(files(), ["thumbnail.png"]);
+    }
+
+    #[test]
+    fn test_variance_uniform() {
+        let xs = vec![77; 8 * 8 * 3];
+        assert_eq!(variance(&frame(&xs, 8, 8 * 3, 8)), 0.);
+    }
+
+    #[test]
+    fn test_variance_checkerboard() {
+        let xs = (0..8 * 8)
+            .flat_map(|i| [if (i % 8 + i / 8) % 2 == 0 { 0 } else { 255 }; 3])
+            .collect::<Vec<u8>>();
+
+        // All components are 127.5 away from the mean
+        assert!(f32::abs(variance(&frame(&xs, 8, 8 * 3, 8)) - 127.5 * 127.5) < 0.1);
+    }
+
+    #[test]
+    fn test_variance_padding() {
+        // Lines of 3 pixels followed by 7 bytes of padding
+        let line = [[10; 9].as_slice(), &[255; 7]].concat();
+        let xs = line.repeat(2);
+
+        assert_eq!(variance(&frame(&xs, 3, 16, 2)), 0.);
+    }
+
+    #[test]
+    fn test_variance_single_pixel() {
+        let xs = [10, 20, 30];
+        assert!(f32::abs(variance(&frame(&xs, 1, 3, 1)) - 200. / 3.) < 0.01);
+    }
+
+    #[test]
+    fn test_configure_decoder_threads() {
+        gst::init().unwrap();
+        // Other tests remove decoders from the registry
+        let _decoders = DECODER_REGISTRY.read().unwrap();
+
+        for name in ["vp8dec", "vp9dec"] {
+            let element = gst::ElementFactory::make(name).build().unwrap();
+            configure_decoder_threads(&element, NonZeroU32::new(3).unwrap());
+            assert_eq!(element.property::<u32>("threads"), 3, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_configure_hevc_decoder_threads() {
+        gst::init().unwrap();
+        let _decoders = DECODER_REGISTRY.read().unwrap();
+
+        let threads = NonZeroU32::new(3).unwrap();
+        let make = |name| gst::ElementFactory::make(name).build().ok();
+
+        // These are optional plugins that might not be installed
+        if let Some(element) = make("openh264dec") {
+            configure_decoder_threads(&element, threads);
+            assert_eq!(element.property::<u32>("num-threads"), 3);
+        }
+        if let Some(element) = make("x265dec") {
+            configure_decoder_threads(&element, threads);
+            assert_eq!(element.property::<i32>("frame-threads"), 3);
+        }
+        if let Some(element) = make("msdkh265dec") {
+            configure_decoder_threads(&element, threads);
+            assert_eq!(element.property::<u32>("num-thread"), 3);
+        }
+    }
+
+    #[test]
+    fn test_configure_av1_decoder_threads() {
+        gst::init().unwrap();
+        let _decoders = DECODER_REGISTRY.read().unwrap();
+
+        let threads = NonZeroU32::new(3).unwrap();
+        let make = |name| gst::ElementFactory::make(name).build().ok();
+
+        if let Some(element) = make("av1dec") {
+            let property = element.class().find_property("n-threads").unwrap();
+            assert_eq!(property.value_type(), glib::Type::U32);
+
+            configure_decoder_threads(&element, threads);
+            assert_eq!(element.property::<u32>("n-threads"), 3);
+        }
+        if let Some(element) = make("rav1edec") {
+            let property = element.class().find_property("max-frame-delay").unwrap();
+            assert_eq!(property.value_type(), glib::Type::U32);
+
+            configure_decoder_threads(&element, threads);
+            assert_eq!(element.property::<u32>("max-frame-delay"), 0);
+            assert!(element.property::<bool>("low-latency"));
+        }
+    }
+
+    #[test]
+    fn test_decoder_threads_in_batch() {
+        let threads = NonZeroU32::new(3).unwrap();
+
+        assert_eq!(DecoderThreads::Auto.threads_in_batch().get(), 1);
+        assert_eq!(DecoderThreads::Fixed(threads).threads_in_batch(), threads);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn test_seek_failure_warning() {
+        gst::init().unwrap();
+
+        // Pipelines in the NULL state can't seek
+        let pipeline = gst::Pipeline::new();
+        let _ = seek(&pipeline, gst::ClockTime::from_seconds(1));
+        assert!(logs_contain("Failed to seek to"));
+    }
+
+    #[test]
+    fn test_state_change_error_details() {
+        gst::init().unwrap();
+
+        let pipeline = gst::Pipeline::new();
+        let err = state_change_error_details(&pipeline);
+        assert!(matches!(err.kind(), ErrorKind::Other(_)));
+
+        pipeline
+            .bus()
+            .unwrap()
+            .post(
+                gst::message::Error::builder(gst::ResourceError::NotFound, "File not found")
+                    .debug("filesrc: No such file")
+                    .build(),
+            )
+            .unwrap();
+        let err = state_change_error_details(&pipeline);
+        assert!(matches!(err.kind(), ErrorKind::GLib(_)));
+        assert_eq!(err.kind().to_string(), "File not found");
+        assert_eq!(err.debug(), Some("filesrc: No such file"));
+    }
+
+    #[test]
+    fn test_seek_failure() {
+        gst::init().unwrap();
+
+        let pipeline = gst::Pipeline::new();
+        let err = seek(&pipeline, gst::ClockTime::from_seconds(1)).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::SeekFailed { position, .. } if *position == gst::ClockTime::from_seconds(1)
+        ));
+    }
+
+    #[cfg(feature = "gst-log")]
+    #[test]
+    fn test_gst_log_bridge() {
+        struct TestLogger(Mutex<Vec<(log::Level, String)>>);
+
+        impl log::Log for TestLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((record.level(), record.args().to_string()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        gst::init().unwrap();
+        // Installing twice must not duplicate messages
+        logging::install_gst_log_bridge();
+        logging::install_gst_log_bridge();
+
+        gst::warning!(gst::CAT_DEFAULT, "bridged warning");
+
+        let records = LOGGER.0.lock().unwrap();
+        let warnings = records
+            .iter()
+            .filter(|(level, message)| {
+                *level == log::Level::Warn && message.contains("bridged warning")
+            })
+            .count();
+        assert_eq!(warnings, 1);
+    }
+
+    #[test]
+    fn test_is_hdr() {
+        gst::init().unwrap();
+
+        let caps = |colorimetry: Option<&str>| {
+            let mut caps = gst::Caps::builder("video/x-h265").field("profile", "main-10");
+            if let Some(colorimetry) = colorimetry {
+                caps = caps.field("colorimetry", colorimetry);
+            }
+            caps.build()
+        };
+
+        assert!(is_hdr(caps(Some("bt2100-pq")).structure(0).unwrap()));
+        assert!(is_hdr(caps(Some("bt2100-hlg")).structure(0).unwrap()));
+        assert!(is_hdr(caps(Some("bt2020-10")).structure(0).unwrap()));
+        assert!(!is_hdr(caps(Some("bt709")).structure(0).unwrap()));
+        assert!(!is_hdr(caps(None).structure(0).unwrap()));
+    }
+
+    #[test]
+    fn test_check_required_elements() {
+        gst::init().unwrap();
+
+        check_required_elements(&["uridecodebin3", "appsink"]).unwrap();
+        let err = check_required_elements(&["videoflip", "nonexistentelement"]).unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::MissingPlugin(name) if name == "nonexistentelement")
+        );
+        assert!(
+            err.to_string()
+                .ends_with("Missing GStreamer plugin: nonexistentelement")
+        );
+
+        assert_eq!(
+            ErrorKind::MissingPlugin("videoflip".into()).to_string(),
+            "Missing GStreamer plugin: videoflip (install gstreamer1-plugins-good)"
+        );
+    }
+
+    #[test]
+    fn test_decoder_registry_reversible() {
+        gst::init().unwrap();
+        let mut decoders = DECODER_REGISTRY.write().unwrap();
+
+        // Most machines running the tests have no hardware decoders
+        gst::Element::register(
+            None,
+            "fakehwdec",
+            gst::Rank::NONE,
+            FakeHwDecoder::static_type(),
+        )
+        .unwrap();
+        let registry = gst::Registry::get();
+        let hw_decoders = registry
+            .features_filtered(filter_hw_decoders, false)
+            .into_iter()
+            .map(|feature| feature.name().to_string())
+            .collect::<Vec<_>>();
+        assert!(hw_decoders.iter().any(|name| name == "fakehwdec"));
+        let available = |name: &str| registry.lookup_feature(name).is_some();
+        let removed = |decoders: &DecoderRegistry| {
+            let mut names = decoders
+                .removed
+                .iter()
+                .map(|feature| feature.name().to_string())
+                .collect::<Vec<_>>();
+            names.sort_unstable();
+            names
+        };
+        assert!(available("vp8dec"));
+
+        decoders.apply(DecoderSettings {
+            allow_hardware: false,
+            allowlist: Some(hw_decoders.clone()),
+            denylist: vec!["vp8dec".into()],
+        });
+        assert!(hw_decoders.iter().all(|name| available(name)));
+        assert!(!available("vp8dec"));
+        assert_eq!(removed(&decoders), ["vp8dec"]);
+
+        decoders.apply(DecoderSettings {
+            allow_hardware: false,
+            allowlist: None,
+            denylist: Vec::new(),
+        });
+        assert!(!available("fakehwdec"));
+        let mut expected = hw_decoders.clone();
+        expected.sort_unstable();
+        assert_eq!(removed(&decoders), expected);
+
+        // Previous changes don't leak into the next settings
+        decoders.apply(DecoderSettings {
+            allow_hardware: true,
+            allowlist: None,
+            denylist: Vec::new(),
+        });
+        assert!(available("vp8dec"));
+        assert!(available("fakehwdec"));
+        assert!(decoders.removed.is_empty());
+    }
+
+    glib::wrapper! {
+        /// Element with the metadata of a hardware video decoder
+        pub struct FakeHwDecoder(ObjectSubclass<fake_hw_decoder::FakeHwDecoder>)
+            @extends gst::Element, gst::Object;
+    }
+
+    mod fake_hw_decoder {
+        use std::sync::LazyLock;
+
+        use gio::glib;
+        use gst::subclass::prelude::*;
+
+        #[derive(Default)]
+        pub struct FakeHwDecoder;
+
+        #[glib::object_subclass]
+        impl ObjectSubclass for FakeHwDecoder {
+            const NAME: &'static str = "GstThumbnailersFakeHwDecoder";
+            type Type = super::FakeHwDecoder;
+            type ParentType = gst::Element;
+        }
+
+        impl ObjectImpl for FakeHwDecoder {}
+        impl GstObjectImpl for FakeHwDecoder {}
+
+        impl ElementImpl for FakeHwDecoder {
+            fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+                static METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+                    gst::subclass::ElementMetadata::new(
+                        "Fake hardware decoder",
+                        "Codec/Decoder/Video/Hardware",
+                        "Decoder that only exists in the registry",
+                        "gst-thumbnailers",
+                    )
+                });
+                Some(&*METADATA)
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_gstreamer_version() {
+        gst::init().unwrap();
+
+        check_gstreamer_version(MINIMUM_GST_VERSION).unwrap();
+        assert!(check_gstreamer_version((u32::MAX, 0, 0, 0)).is_err());
+    }
+}
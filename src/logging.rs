@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::Verbosity;
+
+/// Log a warning via `tracing` if the feature is enabled and to stderr
+/// otherwise
+///
+/// Warnings on stderr are suppressed with [`Verbosity::Quiet`].
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        if $crate::logging::verbosity() > $crate::Verbosity::Quiet {
+            eprintln!($($arg)*);
+        }
+    }};
+}
+
+/// Log a decision of the thumbnailer via `tracing` if the feature is enabled
+/// and to stderr with [`Verbosity::Verbose`] otherwise
+macro_rules! verbose {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        if $crate::logging::verbosity() == $crate::Verbosity::Verbose {
+            eprintln!($($arg)*);
+        }
+    }};
+}
+
+/// Log a debug message via `tracing` if the feature is enabled
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    }};
+}
+
+/// Verbosity of the last initialized configuration
+///
+/// Global like the registry changes in `init`, since the log macros are
+/// also used where no configuration is available.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub(crate) fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+#[cfg_attr(feature = "tracing", allow(dead_code))]
+pub(crate) fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Forward GStreamer's log messages to the `log` crate
+///
+/// Only installs the handler once, no matter how often it's called.
+#[cfg(feature = "gst-log")]
+pub(crate) fn install_gst_log_bridge() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+
+    INSTALLED.call_once(|| {
+        // GStreamer doesn't produce any messages below its threshold
+        if gst::log::default_threshold() < gst::DebugLevel::Warning {
+            gst::log::set_default_threshold(gst::DebugLevel::Warning);
+        }
+        gst::log::remove_default_log_function();
+
+        gst::log::add_log_function(
+            |category, level, _file, _function, _line, _object, message| {
+                let level = match level {
+                    gst::DebugLevel::Error => log::Level::Error,
+                    gst::DebugLevel::Warning | gst::DebugLevel::Fixme => log::Level::Warn,
+                    gst::DebugLevel::Info => log::Level::Info,
+                    gst::DebugLevel::Debug => log::Level::Debug,
+                    _ => log::Level::Trace,
+                };
+
+                if let Some(message) = message.get() {
+                    log::log!(target: "gstreamer", level, "{}: {message}", category.name());
+                }
+            },
+        );
+    });
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use gst::prelude::*;
+
+/// URI for which `uridecodebin3` creates an `appsrc`
+pub(crate) const APPSRC_URI: &str = "appsrc://";
+
+/// Media data in memory that is fed into the pipeline by an `appsrc`
+///
+/// The `appsrc` is seekable, so that frames can be taken from throughout
+/// the video like for files.
+#[derive(Debug, Clone)]
+pub(crate) struct MemorySource {
+    buffer: gst::Buffer,
+    /// Caps from the MIME type hint, which skip type detection
+    caps: Option<gst::Caps>,
+}
+
+impl MemorySource {
+    pub(crate) fn new(data: &[u8], mime_hint: Option<&str>) -> Self {
+        Self {
+            buffer: gst::Buffer::from_slice(data.to_vec()),
+            caps: mime_hint.map(gst::Caps::new_empty_simple),
+        }
+    }
+
+    /// Configure the `appsrc` that `uridecodebin3` created for [`APPSRC_URI`]
+    pub(crate) fn setup(&self, source: &gst::Element) {
+        let Some(appsrc) = source.downcast_ref::<gst_app::AppSrc>() else {
+            return;
+        };
+
+        let size = self.buffer.size() as u64;
+        appsrc.set_caps(self.caps.as_ref());
+        appsrc.set_size(size as i64);
+        appsrc.set_stream_type(gst_app::AppStreamType::SeekableStream);
+
+        // Byte offset from which the next data is pushed
+        let offset = Arc::new(AtomicU64::new(0));
+        let buffer = self.buffer.clone();
+        appsrc.set_callbacks(
+            gst_app::AppSrcCallbacks::builder()
+                .seek_data({
+                    let offset = offset.clone();
+                    move |_, position| {
+                        offset.store(position, Ordering::Relaxed);
+                        position <= size
+                    }
+                })
+                .need_data(move |appsrc, _| {
+                    // Everything after the offset is pushed at once
+                    let start = offset.load(Ordering::Relaxed);
+                    if start < size
+                        && let Ok(mut rest) =
+                            buffer.copy_region(gst::BufferCopyFlags::MEMORY, start as usize..)
+                    {
+                        rest.make_mut().set_offset(start);
+                        let _ = appsrc.push_buffer(rest);
+                    }
+                    let _ = appsrc.end_of_stream();
+                })
+                .build(),
+        );
+    }
+}
@@ -0,0 +1,72 @@
+/// Information about the thumbnailed file stored alongside the image
+///
+/// Only PNG thumbnails can store it, as tEXt chunks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThumbMetadata {
+    /// URI of the thumbnailed file, stored as `Thumb::URI`
+    pub uri: Option<String>,
+    /// Modification time of the thumbnailed file in seconds since the Unix
+    /// epoch, stored as `Thumb::MTime`
+    pub mtime: Option<i64>,
+    /// Start times of the video's chapters, stored as `Thumb::Chapters`
+    pub chapters: Vec<gst::ClockTime>,
+    /// Length of the video, stored in whole seconds as `Thumb::Movie-Length`
+    pub duration: Option<gst::ClockTime>,
+    /// Width and height of the video before scaling, stored as
+    /// `Thumb::Movie-Width` and `Thumb::Movie-Height`
+    pub dimensions: Option<(u32, u32)>,
+    /// Position of the thumbnailed frame in the video, stored in seconds as
+    /// `Thumb::Movie-Offset`, next to the other `Thumb::Movie-*` keys
+    pub movie_offset: Option<gst::ClockTime>,
+}
+
+impl ThumbMetadata {
+    /// Metadata identifying the current version of the file at `uri`
+    ///
+    /// Allows checking if the thumbnail is outdated with
+    /// [`crate::is_thumbnail_valid`].
+    pub fn for_source(uri: &str) -> Self {
+        Self {
+            uri: Some(uri.to_string()),
+            mtime: crate::cache::source_mtime(uri),
+            ..Default::default()
+        }
+    }
+
+    /// Keys and values of the tEXt chunks
+    pub fn text_chunks(&self) -> Vec<(&'static str, String)> {
+        let mut chunks = Vec::new();
+
+        if let Some(uri) = &self.uri {
+            chunks.push(("Thumb::URI", uri.clone()));
+        }
+
+        if let Some(mtime) = self.mtime {
+            chunks.push(("Thumb::MTime", mtime.to_string()));
+        }
+
+        if !self.chapters.is_empty() {
+            let chapters = self
+                .chapters
+                .iter()
+                .map(|start| start.seconds_f64().to_string())
+                .collect::<Vec<_>>();
+            chunks.push(("Thumb::Chapters", chapters.join(",")));
+        }
+
+        if let Some(duration) = self.duration {
+            chunks.push(("Thumb::Movie-Length", duration.seconds().to_string()));
+        }
+
+        if let Some((width, height)) = self.dimensions {
+            chunks.push(("Thumb::Movie-Width", width.to_string()));
+            chunks.push(("Thumb::Movie-Height", height.to_string()));
+        }
+
+        if let Some(offset) = self.movie_offset {
+            chunks.push(("Thumb::Movie-Offset", offset.seconds_f64().to_string()));
+        }
+
+        chunks
+    }
+}
@@ -0,0 +1,48 @@
+use wide::f32x8;
+
+/// SIMD version of [`crate::variance_scalar`] processing eight values at once
+pub fn variance_simd(xs: &[u8], width: u32, stride: u32, height: u32) -> f32 {
+    let effective_stride = width as usize * 3; // format == "RGB"
+    let len = (effective_stride * height as usize) as f32;
+    let lines = || {
+        xs.chunks_exact(stride as usize)
+            .map(|line| &line[0..effective_stride])
+    };
+
+    let avg = lines()
+        .map(|line| {
+            let chunks = line.chunks_exact(8);
+            let rest = chunks.remainder().iter().map(|&x| x as f32).sum::<f32>();
+            chunks
+                .fold(f32x8::ZERO, |acc, chunk| acc + lanes(chunk))
+                .reduce_add()
+                + rest
+        })
+        .sum::<f32>()
+        / len;
+
+    let avg_lanes = f32x8::splat(avg);
+    let sq_diff = lines()
+        .map(|line| {
+            let chunks = line.chunks_exact(8);
+            let rest = chunks
+                .remainder()
+                .iter()
+                .map(|&x| (x as f32 - avg).powi(2))
+                .sum::<f32>();
+            chunks
+                .fold(f32x8::ZERO, |acc, chunk| {
+                    let diff = lanes(chunk) - avg_lanes;
+                    acc + diff * diff
+                })
+                .reduce_add()
+                + rest
+        })
+        .sum::<f32>();
+
+    sq_diff / len
+}
+
+fn lanes(chunk: &[u8]) -> f32x8 {
+    f32x8::from(std::array::from_fn::<f32, 8, _>(|i| chunk[i] as f32))
+}
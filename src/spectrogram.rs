@@ -0,0 +1,284 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+
+use crate::{Error, Result, ThumbnailSource, ThumbnailerConfig};
+
+/// Sample rate at which audio is decoded for the spectrogram
+const SAMPLE_RATE: i32 = 16000;
+/// Samples per STFT window, a power of two for the FFT
+const WINDOW_SIZE: usize = 512;
+const HOP_SIZE: usize = WINDOW_SIZE / 2;
+const N_MELS: usize = 64;
+/// Number of spectra kept before neighboring ones are merged, more than any
+/// thumbnail is wide
+const MAX_SPECTRA: usize = 2048;
+/// Power in dB below which the audio is considered silent
+const SILENCE_DB: f32 = -100.;
+
+/// Power per mel band
+type MelSpectrum = [f32; N_MELS];
+
+/// Mel-scale spectrogram of the audio over its whole length
+pub(crate) fn spectrogram_source(
+    input_uri: &str,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<ThumbnailSource> {
+    let stft = Arc::new(Mutex::new(Stft::new()));
+
+    crate::waveform::decode_audio(input_uri, SAMPLE_RATE, config, cancellable, {
+        let stft = stft.clone();
+        move |samples| stft.lock().unwrap().push_samples(samples)
+    })?;
+
+    let spectra = std::mem::replace(&mut *stft.lock().unwrap(), Stft::new()).finish();
+    if spectra.is_empty() {
+        return Err(Error::other("No audio samples decoded"));
+    }
+
+    let (width, height) = crate::waveform::waveform_dimensions(config.size_mode);
+    let data = render_spectrogram(&spectra, width, height, config.spectrogram_dynamic_range_db);
+
+    Ok(ThumbnailSource::VideoFrame {
+        width,
+        height,
+        data,
+        timestamp: None,
+    })
+}
+
+/// Short-time Fourier transform over Hann windows, reduced to mel bands
+///
+/// To limit memory usage for long audio, the time resolution is halved
+/// whenever [`MAX_SPECTRA`] spectra are stored.
+struct Stft {
+    window: Vec<f32>,
+    /// FFT bins and their weights for each mel band
+    filters: Vec<Vec<(usize, f32)>>,
+    /// Samples not yet covered by a complete window
+    samples: Vec<f32>,
+    spectra: Vec<MelSpectrum>,
+    /// Number of windows averaged into each spectrum
+    windows_per_spectrum: usize,
+    /// Sum and number of the windows for the next spectrum
+    pending: (MelSpectrum, usize),
+}
+
+impl Stft {
+    fn new() -> Self {
+        let window = (0..WINDOW_SIZE)
+            .map(|i| 0.5 - 0.5 * (2. * PI * i as f32 / WINDOW_SIZE as f32).cos())
+            .collect();
+
+        Self {
+            window,
+            filters: mel_filters(),
+            samples: Vec::new(),
+            spectra: Vec::new(),
+            windows_per_spectrum: 1,
+            pending: ([0.; N_MELS], 0),
+        }
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+
+        let mut start = 0;
+        while start + WINDOW_SIZE <= self.samples.len() {
+            let spectrum = self.mel_spectrum(&self.samples[start..start + WINDOW_SIZE]);
+            self.push_spectrum(spectrum);
+            start += HOP_SIZE;
+        }
+        self.samples.drain(..start);
+    }
+
+    fn push_spectrum(&mut self, spectrum: MelSpectrum) {
+        let (sum, count) = &mut self.pending;
+        for (sum, power) in sum.iter_mut().zip(spectrum) {
+            *sum += power;
+        }
+        *count += 1;
+
+        if *count == self.windows_per_spectrum {
+            let n = *count as f32;
+            self.spectra.push(sum.map(|sum| sum / n));
+            self.pending = ([0.; N_MELS], 0);
+        }
+
+        if self.spectra.len() == MAX_SPECTRA {
+            self.spectra = self
+                .spectra
+                .chunks_exact(2)
+                .map(|pair| std::array::from_fn(|mel| (pair[0][mel] + pair[1][mel]) / 2.))
+                .collect();
+            self.windows_per_spectrum *= 2;
+        }
+    }
+
+    /// All spectra, including incomplete ones at the end
+    fn finish(mut self) -> Vec<MelSpectrum> {
+        // Audio shorter than a single window
+        if self.spectra.is_empty() && self.pending.1 == 0 && !self.samples.is_empty() {
+            self.samples.resize(WINDOW_SIZE, 0.);
+            let spectrum = self.mel_spectrum(&self.samples);
+            self.push_spectrum(spectrum);
+        }
+
+        let (sum, count) = self.pending;
+        if count > 0 {
+            self.spectra.push(sum.map(|sum| sum / count as f32));
+        }
+
+        self.spectra
+    }
+
+    fn mel_spectrum(&self, samples: &[f32]) -> MelSpectrum {
+        let mut re = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| sample * weight)
+            .collect::<Vec<_>>();
+        let mut im = vec![0.; WINDOW_SIZE];
+        fft(&mut re, &mut im);
+
+        std::array::from_fn(|mel| {
+            self.filters[mel]
+                .iter()
+                .map(|&(bin, weight)| weight * (re[bin].powi(2) + im[bin].powi(2)))
+                .sum()
+        })
+    }
+}
+
+/// Triangular filters evenly spaced on the mel scale up to the Nyquist
+/// frequency
+fn mel_filters() -> Vec<Vec<(usize, f32)>> {
+    let mel = |hz: f32| 2595. * (1. + hz / 700.).log10();
+    let hz = |mel: f32| 700. * (10f32.powf(mel / 2595.) - 1.);
+    let max_mel = mel(SAMPLE_RATE as f32 / 2.);
+    let bin_width = SAMPLE_RATE as f32 / WINDOW_SIZE as f32;
+
+    // Start, peak, and end of the filters as fractional FFT bins
+    let edges = (0..N_MELS + 2)
+        .map(|i| hz(max_mel * i as f32 / (N_MELS + 1) as f32) / bin_width)
+        .collect::<Vec<_>>();
+
+    edges
+        .windows(3)
+        .map(|edges| {
+            let (lower, center, upper) = (edges[0], edges[1], edges[2]);
+            let filter = (lower.floor() as usize..=upper.ceil() as usize)
+                .filter_map(|bin| {
+                    let bin_f = bin as f32;
+                    let weight = if bin_f <= center {
+                        (bin_f - lower) / (center - lower)
+                    } else {
+                        (upper - bin_f) / (upper - center)
+                    };
+                    (weight > 0.).then_some((bin, weight))
+                })
+                .collect::<Vec<_>>();
+
+            // Low bands can be narrower than a single bin
+            if filter.is_empty() {
+                vec![(center.round() as usize, 1.)]
+            } else {
+                filter
+            }
+        })
+        .collect()
+}
+
+/// In-place radix-2 FFT, the length has to be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2. * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Packed RGB image with time from left to right and high frequencies at
+/// the top
+///
+/// Levels more than `dynamic_range_db` below the loudest one are black.
+fn render_spectrogram(
+    spectra: &[MelSpectrum],
+    width: u32,
+    height: u32,
+    dynamic_range_db: f32,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let dynamic_range_db = dynamic_range_db.max(1.);
+
+    // Average power of the spectra in each column in dB
+    let columns = (0..width)
+        .map(|x| {
+            let start = x * spectra.len() / width;
+            let end = ((x + 1) * spectra.len() / width).max(start + 1);
+            let n = (end - start) as f32;
+            std::array::from_fn::<_, N_MELS, _>(|mel| {
+                let power = spectra[start..end].iter().map(|s| s[mel]).sum::<f32>() / n;
+                f32::max(10. * power.log10(), SILENCE_DB)
+            })
+        })
+        .collect::<Vec<_>>();
+    let max_db = columns.iter().flatten().copied().fold(SILENCE_DB, f32::max);
+
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in (0..height).rev() {
+        // Loudest of the mel bands covered by the row
+        let start = y * N_MELS / height;
+        let end = ((y + 1) * N_MELS / height).max(start + 1);
+        for column in &columns {
+            let db = column[start..end]
+                .iter()
+                .copied()
+                .fold(SILENCE_DB, f32::max);
+            let level = if db <= SILENCE_DB {
+                0.
+            } else {
+                (1. - (max_db - db) / dynamic_range_db).max(0.)
+            };
+            data.extend_from_slice(&heat_map(level));
+        }
+    }
+
+    data
+}
+
+/// Color from black over red and yellow to white for `level` between 0
+/// and 1
+fn heat_map(level: f32) -> [u8; 3] {
+    let channel = |offset: f32| ((level * 3. - offset).clamp(0., 1.) * 255.) as u8;
+    [channel(0.), channel(1.), channel(2.)]
+}
@@ -0,0 +1,93 @@
+use gio::glib;
+
+use crate::{Error, Result};
+
+/// Purpose of an image embedded in the tags of a file
+///
+/// Same values as `GstTagImageType`.
+// TODO: Use gst_tag::TagImageType when it's properly exported
+// See: https://gitlab.gnome.org/sophie-h/gst-thumbnailers/-/issues/4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(i32)]
+pub enum TagImageType {
+    None = -1,
+    Undefined = 0,
+    FrontCover = 1,
+    BackCover = 2,
+    LeafletPage = 3,
+    Medium = 4,
+    LeadArtist = 5,
+    Artist = 6,
+    Conductor = 7,
+    BandOrchestra = 8,
+    Composer = 9,
+    Lyricist = 10,
+    RecordingLocation = 11,
+    DuringRecording = 12,
+    DuringPerformance = 13,
+    VideoCapture = 14,
+    Fish = 15,
+    Illustration = 16,
+    BandArtistLogo = 17,
+    PublisherStudioLogo = 18,
+}
+
+impl TryFrom<i32> for TagImageType {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self> {
+        Ok(match value {
+            -1 => Self::None,
+            0 => Self::Undefined,
+            1 => Self::FrontCover,
+            2 => Self::BackCover,
+            3 => Self::LeafletPage,
+            4 => Self::Medium,
+            5 => Self::LeadArtist,
+            6 => Self::Artist,
+            7 => Self::Conductor,
+            8 => Self::BandOrchestra,
+            9 => Self::Composer,
+            10 => Self::Lyricist,
+            11 => Self::RecordingLocation,
+            12 => Self::DuringRecording,
+            13 => Self::DuringPerformance,
+            14 => Self::VideoCapture,
+            15 => Self::Fish,
+            16 => Self::Illustration,
+            17 => Self::BandArtistLogo,
+            18 => Self::PublisherStudioLogo,
+            _ => return Err(Error::other(format!("Unknown image type {value}"))),
+        })
+    }
+}
+
+impl TagImageType {
+    /// Type from the `image-type` field of an image sample's caps
+    ///
+    /// Images without the field are of type [`TagImageType::None`].
+    pub fn from_caps(caps: &gst::CapsRef) -> Result<Self> {
+        let Some(value) = caps.structure(0).and_then(|s| s.value("image-type").ok()) else {
+            return Ok(Self::None);
+        };
+
+        // The field holds a GstTagImageType enum, not a plain integer
+        let value = value
+            .get::<i32>()
+            .ok()
+            .or_else(|| glib::EnumValue::from_value(value).map(|(_, v)| v.value()))
+            .ok_or_else(|| Error::other(format!("Invalid image type {value:?}")))?;
+
+        Self::try_from(value)
+    }
+
+    /// Lower values are preferred as thumbnail
+    pub(crate) fn priority(self) -> i32 {
+        match self {
+            Self::FrontCover => -2,
+            // Usually the cover if a file only has one image
+            Self::None | Self::Undefined => -1,
+            other => other as i32,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use gio::prelude::*;
+
+use crate::{Error, Result};
+
+/// Output path for a thumbnail derived from the source file
+///
+/// Supported placeholders:
+///
+/// - `%b`: File name of the source without extension
+/// - `%d`: Directory of the source, only for local files
+/// - `%e`: Extension of the source without the dot
+/// - `%W`: Width of the thumbnail
+/// - `%H`: Height of the thumbnail
+/// - `%%`: A literal `%`
+///
+/// A `%` that isn't part of one of these is kept as is.
+pub fn expand_template(
+    template: &str,
+    source_uri: &str,
+    dimensions: (u32, u32),
+) -> Result<PathBuf> {
+    let file = gio::File::for_uri(source_uri);
+    let name = file
+        .basename()
+        .ok_or_else(|| Error::other(format!("No file name in {source_uri}")))?;
+
+    let mut path = OsString::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c.encode_utf8(&mut [0; 4]));
+            continue;
+        }
+
+        match chars.next_if(|c| PLACEHOLDERS.contains(c)) {
+            Some('b') => path.push(name.file_stem().unwrap_or_default()),
+            Some('d') => {
+                let dir = file
+                    .parent()
+                    .and_then(|parent| parent.path())
+                    .ok_or_else(|| Error::other(format!("No local directory for {source_uri}")))?;
+                path.push(dir);
+            }
+            Some('e') => path.push(name.extension().unwrap_or_default()),
+            Some('W') => path.push(dimensions.0.to_string()),
+            Some('H') => path.push(dimensions.1.to_string()),
+            // `%%` or a `%` that isn't part of a placeholder
+            _ => path.push("%"),
+        }
+    }
+
+    Ok(PathBuf::from(path))
+}
+
+/// Characters following `%` in the placeholders of [`expand_template`]
+const PLACEHOLDERS: [char; 6] = ['b', 'd', 'e', 'W', 'H', '%'];
+
+/// Whether `path` contains any placeholder of [`expand_template`]
+pub(crate) fn is_template(path: &str) -> bool {
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' && chars.next().is_some_and(|c| PLACEHOLDERS.contains(&c)) {
+            return true;
+        }
+    }
+
+    false
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use crate::{Result, ThumbnailerConfig};
+
+/// Creates thumbnails with a fixed configuration
+///
+/// GStreamer is initialized once when the thumbnailer is created. The
+/// thumbnailer can be shared between threads, every thumbnail gets its own
+/// pipeline.
+pub struct Thumbnailer {
+    config: ThumbnailerConfig,
+}
+
+impl Thumbnailer {
+    pub fn new(config: ThumbnailerConfig) -> Result<Self> {
+        crate::init(&config)?;
+
+        Ok(Self { config })
+    }
+
+    pub fn config(&self) -> &ThumbnailerConfig {
+        &self.config
+    }
+
+    pub fn thumbnail_video(&self, uri: &str, output_path: &Path) -> Result<()> {
+        crate::create_video_thumbnail(
+            uri,
+            output_path,
+            &self.config,
+            self.config.decoder_threads.threads(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn thumbnail_audio(&self, uri: &str, output_path: &Path) -> Result<()> {
+        crate::create_audio_thumbnail(uri, output_path, &self.config, None)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::{Error, Pipeline, Result, SizeMode, ThumbnailSource, ThumbnailerConfig};
+
+/// Sample rate at which audio is decoded for the waveform
+const SAMPLE_RATE: i32 = 8000;
+/// Number of samples that are reduced to a single peak, 10 ms
+const BLOCK_SIZE: usize = SAMPLE_RATE as usize / 100;
+
+const BACKGROUND: [u8; 3] = [0x24, 0x1f, 0x31];
+const FOREGROUND: [u8; 3] = [0x99, 0xc1, 0xf1];
+
+/// Image of the peak amplitude of the audio over its whole length
+pub(crate) fn waveform_source(
+    input_uri: &str,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+) -> Result<ThumbnailSource> {
+    let peaks = Arc::new(Mutex::new(Vec::new()));
+
+    let mut block = (0., 0);
+    decode_audio(input_uri, SAMPLE_RATE, config, cancellable, {
+        let peaks = peaks.clone();
+        move |samples| {
+            let mut peaks = peaks.lock().unwrap();
+            for sample in samples {
+                block = (f32::max(block.0, sample.abs()), block.1 + 1);
+                if block.1 == BLOCK_SIZE {
+                    peaks.push(block.0);
+                    block = (0., 0);
+                }
+            }
+        }
+    })?;
+
+    let peaks = std::mem::take(&mut *peaks.lock().unwrap());
+    if peaks.is_empty() {
+        return Err(Error::other("No audio samples decoded"));
+    }
+
+    let (width, height) = waveform_dimensions(config.size_mode);
+    let data = render_waveform(&peaks, width, height);
+
+    Ok(ThumbnailSource::VideoFrame {
+        width,
+        height,
+        data,
+        timestamp: None,
+    })
+}
+
+/// Decode the first audio stream to mono samples at `rate`
+///
+/// The samples are passed to `on_samples` in the order they are decoded.
+pub(crate) fn decode_audio(
+    input_uri: &str,
+    rate: i32,
+    config: &ThumbnailerConfig,
+    cancellable: Option<&gio::Cancellable>,
+    mut on_samples: impl FnMut(&[f32]) + Send + 'static,
+) -> Result<()> {
+    crate::check_cancelled(cancellable)?;
+    crate::check_required_elements(&["uridecodebin3", "audioconvert", "audioresample", "appsink"])?;
+
+    let _decoders = crate::DecoderRegistry::for_config(config);
+    let pipeline = Pipeline::new("gst-audio-waveform");
+
+    // Source
+    let uridecodebin = gst::ElementFactory::make("uridecodebin3")
+        .property("uri", input_uri)
+        .build()?;
+
+    // Filters
+    let audioconvert = gst::ElementFactory::make("audioconvert").build()?;
+    let audioresample = gst::ElementFactory::make("audioresample").build()?;
+
+    // Sink
+    let format = if cfg!(target_endian = "little") {
+        "F32LE"
+    } else {
+        "F32BE"
+    };
+    let caps = gst::Caps::builder("audio/x-raw")
+        .field("format", format)
+        .field("layout", "interleaved")
+        .field("rate", rate)
+        .field("channels", 1)
+        .build();
+    let appsink = gst_app::AppSink::builder()
+        .sync(false)
+        .caps(&caps)
+        .callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+                    debug!(
+                        "Pulled {} bytes of audio at {}",
+                        buffer.size(),
+                        buffer.pts().display()
+                    );
+                    let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+
+                    let samples = map
+                        .chunks_exact(4)
+                        .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+                        .collect::<Vec<_>>();
+                    on_samples(&samples);
+
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        )
+        .build();
+
+    let filters = [
+        &audioconvert,
+        &audioresample,
+        appsink.upcast_ref::<gst::Element>(),
+    ];
+    pipeline.add(&uridecodebin)?;
+    pipeline.add_many(filters)?;
+    gst::Element::link_many(filters)?;
+
+    // Only link the first audio stream
+    let sink_pad = audioconvert.static_pad("sink").unwrap();
+    uridecodebin.connect_pad_added(move |_, src_pad| {
+        // Caps aren't necessarily set yet when uridecodebin3 adds the pad
+        let is_audio = src_pad
+            .stream()
+            .is_some_and(|stream| stream.stream_type().contains(gst::StreamType::AUDIO));
+        if is_audio && !sink_pad.is_linked() {
+            let _ = src_pad.link(&sink_pad);
+        }
+    });
+
+    pipeline.set_state_with_timeout(
+        gst::State::Playing,
+        config.pipeline_timeout,
+        cancellable,
+        "start",
+    )?;
+
+    while let Some(message) = pipeline.pop_message(config.pipeline_timeout, "audio decoding")? {
+        crate::check_cancelled(cancellable)?;
+
+        match message.view() {
+            gst::MessageView::StreamCollection(collection) => {
+                // Without selecting it, uridecodebin3 also decodes a video
+                // stream like the cover art
+                let collection = collection.stream_collection();
+                if let Some(stream_id) = collection
+                    .iter()
+                    .find(|stream| stream.stream_type() == gst::StreamType::AUDIO)
+                    .and_then(|stream| stream.stream_id())
+                {
+                    uridecodebin.send_event(gst::event::SelectStreams::new([stream_id.as_str()]));
+                }
+            }
+            gst::MessageView::Eos(_) => return Ok(()),
+            gst::MessageView::Error(err) => {
+                return Err(Error::other(format!("Failed decoding audio: {err}")));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Waveforms and spectrograms fill the whole thumbnail, with a 2:1 aspect
+/// ratio for [`SizeMode::Fit`]
+pub(crate) fn waveform_dimensions(size_mode: SizeMode) -> (u32, u32) {
+    let (width, height) = match size_mode {
+        SizeMode::Fit { max } => (max, max / 2),
+        SizeMode::Exact { width, height, .. } => (width, height),
+        SizeMode::CropFill { size } => (size, size),
+    };
+
+    (u32::from(width).max(1), u32::from(height).max(1))
+}
+
+/// Packed RGB image with a vertical line per column spanning the highest
+/// peak in that part of the audio
+fn render_waveform(peaks: &[f32], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut data = BACKGROUND.repeat(width * height);
+
+    let center = height as f32 / 2.;
+    for x in 0..width {
+        let start = x * peaks.len() / width;
+        let end = ((x + 1) * peaks.len() / width).max(start + 1);
+        let peak = peaks[start..end].iter().copied().fold(0., f32::max).min(1.);
+
+        // Silence is still drawn as a line
+        let extent = f32::max(peak * center, 0.5);
+        let top = (center - extent).floor() as usize;
+        let bottom = ((center + extent).ceil() as usize).min(height);
+        for y in top..bottom {
+            let i = (y * width + x) * 3;
+            data[i..i + 3].copy_from_slice(&FOREGROUND);
+        }
+    }
+
+    data
+}
@@ -1,3 +1,6 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
 use gio::prelude::*;
 
 #[test]
@@ -12,12 +15,7 @@ fn test_video_thumbnailer() {
         ("1-cover.mkv", 5118.),
     ] {
         let frame = run_video_thumbnailer(path).unwrap();
-        let var = gst_thumbnailers::variance(
-            &frame.buf_bytes(),
-            frame.width(),
-            frame.stride(),
-            frame.height(),
-        );
+        let var = frame_variance(&frame);
 
         assert!(
             f32::abs(var - var_ref) < 200.,
@@ -26,13 +24,1214 @@ fn test_video_thumbnailer() {
     }
 }
 
+#[test]
+fn test_video_thumbnailer_exact_size() {
+    let output = "tests/test-video-exact-output.png";
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-i",
+        &gio::File::for_path("tests/1.webm").uri(),
+        "-o",
+        output,
+        "--size-mode",
+        "exact",
+        "--width",
+        "256",
+        "--height",
+        "256",
+    ])
+    .unwrap();
+
+    let frame = read_png(output);
+    assert_eq!((frame.width(), frame.height()), (256, 256));
+
+    // The 128×72 video is centered with black rows above and below
+    let bytes = frame.buf_bytes();
+    let row = |y: u32| {
+        let start = (y * frame.stride()) as usize;
+        &bytes[start..start + 256 * 3]
+    };
+    assert!(row(0).iter().all(|&x| x == 0));
+    assert!(row(255).iter().all(|&x| x == 0));
+    assert!(row(128).iter().any(|&x| x != 0));
+}
+
+#[test]
+fn test_video_thumbnailer_crop_fill() {
+    let thumbnail = |output: &str, size_mode: &str, size: &str| {
+        gst_thumbnailers::main_video_thumbnailer([
+            "gst-video-thumbnailer",
+            "-i",
+            &gio::File::for_path("tests/1.webm").uri(),
+            "-o",
+            output,
+            "--size-mode",
+            size_mode,
+            "-s",
+            size,
+        ])
+        .unwrap();
+        read_png(output)
+    };
+
+    // Both scale the 128×72 video to 114×64
+    let fit = thumbnail("tests/test-video-crop-fit-output.png", "fit", "114");
+    let crop = thumbnail("tests/test-video-crop-fill-output.png", "crop-fill", "64");
+    assert_eq!((fit.width(), fit.height()), (114, 64));
+    assert_eq!((crop.width(), crop.height()), (64, 64));
+
+    // The cropped thumbnail is the center of the fitted one
+    let (fit_bytes, crop_bytes) = (fit.buf_bytes(), crop.buf_bytes());
+    for y in 0..64 {
+        let fit_start = (y * fit.stride() + 25 * 3) as usize;
+        let crop_start = (y * crop.stride()) as usize;
+        assert_eq!(
+            &fit_bytes[fit_start..fit_start + 64 * 3],
+            &crop_bytes[crop_start..crop_start + 64 * 3]
+        );
+    }
+}
+
+#[test]
+fn test_video_thumbnailer_terminated() {
+    use std::io::Write;
+
+    let output = "tests/test-video-terminated-output.png";
+    let _ = std::fs::remove_file(output);
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_gst-video-thumbnailer"))
+        .args(["-p", "-", "-o", output, "-s", "256"])
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Only the start of the header is written and stdin is kept open, so the
+    // pipeline can't preroll and waits for more data until it's terminated
+    let mut stdin = child.stdin.take().unwrap();
+    let data = std::fs::read("tests/long.webm").unwrap();
+    stdin.write_all(&data[..256]).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    std::process::Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    assert!(!child.wait().unwrap().success());
+    drop(stdin);
+
+    assert!(!Path::new(output).exists());
+    let leftovers = std::fs::read_dir("tests")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("test-video-terminated-output.png."))
+        .collect::<Vec<_>>();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+#[test]
+fn test_create_output_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("a/b/thumbnail.png");
+
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "256",
+        "--create-output-dir",
+    ])
+    .unwrap();
+
+    let frame = read_png(output.to_str().unwrap());
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[test]
+fn test_output_format_from_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("thumbnail.jpg");
+
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "256",
+    ])
+    .unwrap();
+
+    // JPEG start of image marker
+    let data = std::fs::read(&output).unwrap();
+    assert_eq!(data[..3], [0xFF, 0xD8, 0xFF]);
+}
+
+#[test]
+fn test_jpeg_quality() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_size = |quality: &str| {
+        let output = dir.path().join(format!("thumbnail-{quality}.jpg"));
+        gst_thumbnailers::main_video_thumbnailer([
+            "gst-video-thumbnailer",
+            "-p",
+            "tests/1.webm",
+            "-o",
+            output.to_str().unwrap(),
+            "-s",
+            "256",
+            "--jpeg-quality",
+            quality,
+        ])
+        .unwrap();
+        std::fs::metadata(output).unwrap().len()
+    };
+
+    assert!(file_size("50") < file_size("95"));
+}
+
+#[test]
+fn test_video_thumbnailer_not_seekable() {
+    use std::os::fd::AsRawFd;
+
+    // Reading from a pipe doesn't allow seeking
+    let mut cat = std::process::Command::new("cat")
+        .arg("tests/1.webm")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pipe = cat.stdout.take().unwrap();
+
+    let output = "tests/test-video-not-seekable-output.png";
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-i",
+        &format!("fd://{}", pipe.as_raw_fd()),
+        "-o",
+        output,
+        "-s",
+        "256",
+    ])
+    .unwrap();
+
+    let frame = read_png(output);
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+
+    drop(pipe);
+    cat.wait().unwrap();
+}
+
+#[test]
+fn test_video_stream_selector() {
+    // Contains a 90×50 video stream followed by a 128×72 one
+    let uri = gio::File::for_path("tests/multi-video.mkv").uri();
+
+    for (selector, output) in [
+        (
+            gst_thumbnailers::VideoStreamSelector::HighestResolution,
+            "tests/test-video-highest-resolution-output.png",
+        ),
+        (
+            gst_thumbnailers::VideoStreamSelector::StreamIndex(1),
+            "tests/test-video-stream-index-output.png",
+        ),
+    ] {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            video_stream_selector: selector,
+            ..Default::default()
+        };
+        gst_thumbnailers::thumbnail_video(&uri, Path::new(output), &config, None).unwrap();
+
+        let frame = read_png(output);
+        assert_eq!((frame.width(), frame.height()), (128, 72), "{selector:?}");
+    }
+}
+
+#[test]
+fn test_video_thumbnailer_chapter() {
+    // Chapters start at 0, 10, and 25 seconds
+    let thumbnail = |video: &str, chapter: &str| {
+        let output = format!("tests/test-video-chapter-{chapter}-output-{video}.png");
+        gst_thumbnailers::main_video_thumbnailer([
+            "gst-video-thumbnailer",
+            "-p",
+            &format!("tests/{video}"),
+            "-o",
+            &output,
+            "-s",
+            "256",
+            "--chapter",
+            chapter,
+        ])
+        .unwrap();
+
+        read_png(&output).buf_bytes()
+    };
+
+    assert_ne!(
+        thumbnail("chapters.mkv", "0"),
+        thumbnail("chapters.mkv", "2")
+    );
+    // Without chapters the usual frame selection is used
+    assert_eq!(
+        thumbnail("chapters.mkv", "3"),
+        run_video_thumbnailer("chapters.mkv").unwrap().buf_bytes()
+    );
+    thumbnail("1.webm", "0");
+}
+
+#[test]
+fn test_video_thumbnailer_length_metadata() {
+    let output = "tests/test-video-length-output.png";
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output,
+        "-s",
+        "256",
+    ])
+    .unwrap();
+
+    let chunks = png_text_chunks(output);
+    let length = chunks
+        .iter()
+        .find(|(key, _)| key == "Thumb::Movie-Length")
+        .map(|(_, value)| value.parse::<u64>().unwrap());
+    assert_eq!(length, Some(33), "{chunks:?}");
+}
+
+#[test]
+fn test_video_thumbnailer_dimensions_metadata() {
+    // Dimensions before scaling to 64×36
+    let output = "tests/test-video-dimensions-output.png";
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output,
+        "-s",
+        "64",
+    ])
+    .unwrap();
+
+    let frame = read_png(output);
+    assert_eq!((frame.width(), frame.height()), (64, 36));
+
+    let chunks = png_text_chunks(output);
+    for (key, value) in [("Thumb::Movie-Width", "128"), ("Thumb::Movie-Height", "72")] {
+        assert!(
+            chunks.contains(&(key.into(), value.into())),
+            "{key}: {chunks:?}"
+        );
+    }
+}
+
+#[test]
+fn test_video_thumbnailer_chapters_metadata() {
+    run_video_thumbnailer("chapters.mkv").unwrap();
+    let chunks = png_text_chunks("tests/test-video-output-chapters.mkv.png");
+    assert!(
+        chunks.contains(&("Thumb::Chapters".into(), "0,10,25".into())),
+        "{chunks:?}"
+    );
+
+    run_video_thumbnailer("1.webm").unwrap();
+    let chunks = png_text_chunks("tests/test-video-output-1.webm.png");
+    assert!(
+        !chunks.iter().any(|(key, _)| key == "Thumb::Chapters"),
+        "{chunks:?}"
+    );
+}
+
+#[test]
+fn test_video_thumbnailer_movie_offset() {
+    run_video_thumbnailer("1.webm").unwrap();
+    let chunks = png_text_chunks("tests/test-video-output-1.webm.png");
+    let (_, offset) = chunks
+        .iter()
+        .find(|(key, _)| key == "Thumb::Movie-Offset")
+        .unwrap_or_else(|| panic!("{chunks:?}"));
+    let offset = offset.parse::<f64>().unwrap();
+    assert!((0. ..34.).contains(&offset), "{offset}");
+
+    // All movie keys follow the same hyphenated scheme
+    let mut keys = chunks
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .filter(|key| key.starts_with("Thumb::Movie"))
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    assert_eq!(
+        keys,
+        [
+            "Thumb::Movie-Height",
+            "Thumb::Movie-Length",
+            "Thumb::Movie-Offset",
+            "Thumb::Movie-Width"
+        ]
+    );
+
+    let data = std::fs::read("tests/1.webm").unwrap();
+    let source = gst_thumbnailers::thumbnail_video_from_bytes(&data, None, 256).unwrap();
+    let gst_thumbnailers::ThumbnailSource::VideoFrame { timestamp, .. } = source else {
+        panic!("Expected a video frame, got {source:?}");
+    };
+    assert_eq!(timestamp.map(|ts| ts.seconds_f64()), Some(offset));
+}
+
+#[test]
+fn test_video_thumbnailer_rotation() {
+    // The test video has no orientation tag
+    for (rotation, dimensions) in [("0", (128, 72)), ("90", (72, 128)), ("270", (72, 128))] {
+        let output = format!("tests/test-video-rotation-{rotation}-output.png");
+        gst_thumbnailers::main_video_thumbnailer([
+            "gst-video-thumbnailer",
+            "-p",
+            "tests/1.webm",
+            "-o",
+            &output,
+            "-s",
+            "256",
+            "--rotation",
+            rotation,
+        ])
+        .unwrap();
+
+        let frame = read_png(&output);
+        assert_eq!((frame.width(), frame.height()), dimensions, "{rotation}");
+    }
+}
+
+#[test]
+fn test_video_thumbnailer_hw_decoder_allowlist() {
+    // Works whether or not the decoders are installed
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        "tests/test-video-hw-allowlist-output.png",
+        "-s",
+        "256",
+        "--allow-hw-decoder",
+        "nvvp8dec",
+        "vavp8dec",
+    ])
+    .unwrap();
+
+    let frame = read_png("tests/test-video-hw-allowlist-output.png");
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[test]
+fn test_video_thumbnailer_decoder_denylist() {
+    // Unknown decoders are ignored
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        "tests/test-video-denylist-output.png",
+        "-s",
+        "256",
+        "--disable-decoder",
+        "fakevideodec",
+    ])
+    .unwrap();
+
+    let frame = read_png("tests/test-video-denylist-output.png");
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[test]
+fn test_decoder_denylist_per_config() {
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let output = Path::new("tests/test-video-denylist-per-config-output.png");
+    let denylist_config = gst_thumbnailers::ThumbnailerConfig {
+        decoder_denylist: vec!["vp8dec".into(), "avdec_vp8".into()],
+        ..Default::default()
+    };
+    let config = gst_thumbnailers::ThumbnailerConfig::default();
+
+    // The denylist doesn't stay in effect for later configurations
+    gst_thumbnailers::thumbnail_video(&uri, output, &denylist_config, None).unwrap_err();
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap();
+
+    let request = |id, config_override| gst_thumbnailers::ThumbnailRequest {
+        id,
+        source_uri: uri.clone(),
+        output_path: format!("tests/test-video-denylist-per-config-{id}.png").into(),
+        kind: gst_thumbnailers::ThumbnailerKind::Video,
+        config_override,
+    };
+    let denylist_config = gst_thumbnailers::ThumbnailerConfig {
+        decoder_denylist: vec!["vp8dec".into(), "avdec_vp8".into()],
+        ..Default::default()
+    };
+    let results = gst_thumbnailers::thumbnail_batch_parallel(
+        &[
+            request(0, None),
+            request(1, Some(denylist_config)),
+            request(2, None),
+        ],
+        &config,
+        3,
+    );
+    assert!(results[0].outcome.is_ok());
+    assert!(results[1].outcome.is_err());
+    assert!(results[2].outcome.is_ok());
+}
+
+#[test]
+fn test_video_thumbnailer_without_rotation() {
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        apply_rotation: false,
+        // Ignored without rotation
+        forced_rotation: Some(gst_video::VideoOrientationMethod::_90r),
+        ..Default::default()
+    };
+
+    let bytes = gst_thumbnailers::thumbnail_video_to_bytes(&uri, &config, None).unwrap();
+    let frame = decode(bytes);
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[test]
+fn test_thumbnail_to_bytes() {
+    let config = gst_thumbnailers::ThumbnailerConfig::default();
+
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let data = gst_thumbnailers::thumbnail_video_to_bytes(&uri, &config, None).unwrap();
+    let frame = decode(data);
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+
+    let uri = gio::File::for_path("tests/audio-cover-png.flac").uri();
+    let data = gst_thumbnailers::thumbnail_audio_to_bytes(&uri, &config, None).unwrap();
+    let frame = decode(data);
+    assert_eq!((frame.width(), frame.height()), (200, 200));
+}
+
+#[test]
+fn test_encode_to_bytes() {
+    let source = gst_thumbnailers::ThumbnailSource::VideoFrame {
+        width: 4,
+        height: 2,
+        data: vec![255; 4 * 2 * 3],
+        timestamp: None,
+    };
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        size_mode: gst_thumbnailers::SizeMode::Exact {
+            width: 8,
+            height: 8,
+            background: [0, 0, 0],
+        },
+        ..Default::default()
+    };
+
+    let frame = decode(source.encode_to_bytes(&config).unwrap());
+    assert_eq!((frame.width(), frame.height()), (8, 8));
+    // Only the center is white
+    let bytes = frame.buf_bytes();
+    assert_eq!(bytes[0], 0);
+    assert_eq!(bytes[(3 * frame.stride() + 2 * 3) as usize], 255);
+}
+
+#[test]
+fn test_video_thumbnailer_anamorphic() {
+    // 128×72 frames with a display size of 144×72, i.e. a pixel-aspect-ratio
+    // of 9:8
+    let frame = run_video_thumbnailer("anamorphic.mkv").unwrap();
+    assert_eq!((frame.width(), frame.height()), (144, 72));
+}
+
+#[test]
+fn test_video_thumbnailer_dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("corrupt.webm");
+    let output = dir.path().join("thumbnail.png");
+    let dry_run = |input: &Path| {
+        gst_thumbnailers::main_video_thumbnailer([
+            "gst-video-thumbnailer",
+            "-p",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-s",
+            "256",
+            "--dry-run",
+        ])
+    };
+
+    dry_run(Path::new("tests/1.webm")).unwrap();
+    assert!(!output.exists());
+
+    std::fs::write(&input, b"not a video").unwrap();
+    assert!(dry_run(&input).is_err());
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_video_thumbnailer_verbose() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("thumbnail.png");
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "256",
+        "-v",
+    ])
+    .unwrap();
+    assert!(output.exists());
+}
+
+#[test]
+fn test_video_thumbnailer_stdin() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let output = "tests/test-video-output-stdin.png";
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gst-video-thumbnailer"))
+        .args(["-p", "-", "-o", output, "-s", "256"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let video = std::fs::read("tests/1.webm").unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    // The thumbnailer stops reading after the first frame
+    let _ = stdin.write_all(&video);
+    drop(stdin);
+
+    assert!(child.wait().unwrap().success());
+    // Fitting doesn't scale up the 128×72 video
+    let frame = read_png(output);
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[test]
+fn test_video_thumbnail_from_bytes() {
+    let data = std::fs::read("tests/1.webm").unwrap();
+    let source =
+        gst_thumbnailers::thumbnail_video_from_bytes(&data, Some("video/webm"), 256).unwrap();
+    assert!(source.is_video_frame());
+    assert!(source.as_cover_art().is_none());
+    let (width, height, _) = source.as_video_frame().unwrap();
+    // Same as thumbnailing the file
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        size_mode: gst_thumbnailers::SizeMode::Fit { max: 256 },
+        ..Default::default()
+    };
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let expected = decode(gst_thumbnailers::thumbnail_video_to_bytes(&uri, &config, None).unwrap());
+    assert_eq!((width, height), (expected.width(), expected.height()));
+    assert_eq!((width, height), (128, 72));
+
+    // Without a hint the format is detected
+    gst_thumbnailers::thumbnail_video_from_bytes(&data, None, 256).unwrap();
+
+    // Embedded cover art is preferred over frames
+    let data = std::fs::read("tests/1-cover.mkv").unwrap();
+    let source = gst_thumbnailers::thumbnail_video_from_bytes(&data, None, 256).unwrap();
+    assert!(source.is_cover_art());
+    assert!(source.as_video_frame().is_none());
+    assert!(source.as_cover_art().is_some());
+}
+
+#[test]
+fn test_thumbnail_source_dimensions() {
+    let source = gst_thumbnailers::ThumbnailSource::VideoFrame {
+        width: 4,
+        height: 2,
+        data: vec![0; 4 * 2 * 3],
+        timestamp: None,
+    };
+    assert_eq!(source.dimensions().unwrap(), (4, 2));
+
+    gst::init().unwrap();
+    let cover_art = |data: Vec<u8>| {
+        gst_thumbnailers::ThumbnailSource::CoverArt(
+            gst::Sample::builder()
+                .buffer(&gst::Buffer::from_slice(data))
+                .build(),
+        )
+    };
+    let mut ppm = b"P6 4 2 255\n".to_vec();
+    ppm.extend([128; 4 * 2 * 3]);
+    assert_eq!(cover_art(ppm).dimensions().unwrap(), (4, 2));
+
+    // Broken images aren't reported with a size
+    cover_art(b"not an image".to_vec())
+        .dimensions()
+        .unwrap_err();
+}
+
+#[test]
+fn test_thumbnail_source_display() {
+    let source = gst_thumbnailers::ThumbnailSource::VideoFrame {
+        width: 320,
+        height: 240,
+        data: vec![0; 320 * 240 * 3],
+        timestamp: Some(gst::ClockTime::from_mseconds(3500)),
+    };
+    assert_eq!(
+        source.to_string(),
+        "VideoFrame(320×240, 230400 bytes, ts=3s500ms)"
+    );
+
+    gst::init().unwrap();
+    let mut ppm = b"P6 4 2 255\n".to_vec();
+    ppm.extend([128; 4 * 2 * 3]);
+    let size = ppm.len();
+    let source = gst_thumbnailers::ThumbnailSource::CoverArt(
+        gst::Sample::builder()
+            .buffer(&gst::Buffer::from_slice(ppm))
+            .caps(&gst::Caps::new_empty_simple("image/x-portable-pixmap"))
+            .build(),
+    );
+    assert_eq!(
+        source.to_string(),
+        format!("CoverArt(image/x-portable-pixmap, {size} bytes)")
+    );
+}
+
+#[test]
+fn test_video_thumbnail_from_bytes_formats() {
+    let config = gst_thumbnailers::ThumbnailerConfig::default();
+    for (video, mime) in [
+        ("1.webm", "video/webm"),
+        ("2.webm", "video/webm"),
+        ("3.webm", "video/webm"),
+        ("long.webm", "video/webm"),
+        ("short.webm", "video/webm"),
+        ("uneven.webm", "video/webm"),
+        ("anamorphic.mkv", "video/x-matroska"),
+        ("chapters.mkv", "video/x-matroska"),
+        ("multi-video.mkv", "video/x-matroska"),
+        ("1-cover.mkv", "video/x-matroska"),
+    ] {
+        let path = format!("tests/{video}");
+        let data = std::fs::read(&path).unwrap();
+        let uri = gio::File::for_path(&path).uri();
+        let expected =
+            decode(gst_thumbnailers::thumbnail_video_to_bytes(&uri, &config, None).unwrap());
+
+        for hint in [Some(mime), None] {
+            let source = gst_thumbnailers::thumbnail_video_from_bytes(&data, hint, 256)
+                .unwrap_or_else(|err| panic!("{video}: {err}"));
+            if let Some((width, height, _)) = source.as_video_frame() {
+                assert_eq!(
+                    (width, height),
+                    (expected.width(), expected.height()),
+                    "{video}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_xdg_thumbnail_path() {
+    // Example from the thumbnail specification
+    let path = gst_thumbnailers::xdg_thumbnail_path(
+        "file:///home/jens/photos/me.png",
+        gst_thumbnailers::ThumbnailFlavor::Large,
+    );
+    assert!(path.ends_with("thumbnails/large/c6ee772d9e49320e97ec29a7eb5b1697.png"));
+}
+
+#[test]
+fn test_video_thumbnailer_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_gst-video-thumbnailer"))
+        .args(["-p", "tests/1.webm", "--cache", "normal"])
+        .env("XDG_CACHE_HOME", dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let uri = gio::File::for_path(std::fs::canonicalize("tests/1.webm").unwrap()).uri();
+    let hash = gio::glib::compute_checksum_for_string(gio::glib::ChecksumType::Md5, &uri).unwrap();
+    let output = dir.path().join(format!("thumbnails/normal/{hash}.png"));
+    let frame = read_png(output.to_str().unwrap());
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[test]
+fn test_is_thumbnail_valid() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("thumbnail.png");
+    let uri = gio::File::for_path(std::fs::canonicalize("tests/1.webm").unwrap()).uri();
+    let mtime = std::fs::metadata("tests/1.webm")
+        .unwrap()
+        .modified()
+        .unwrap()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    assert!(!gst_thumbnailers::is_thumbnail_valid(&output, &uri, mtime));
+
+    gst_thumbnailers::thumbnail_video(&uri, &output, &Default::default(), None).unwrap();
+    assert!(gst_thumbnailers::is_thumbnail_valid(&output, &uri, mtime));
+    assert!(!gst_thumbnailers::is_thumbnail_valid(
+        &output,
+        "file:///other.webm",
+        mtime
+    ));
+    assert!(!gst_thumbnailers::is_thumbnail_valid(
+        &output,
+        &uri,
+        mtime + 1
+    ));
+
+    // Only the size differs, so the thumbnail is kept
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "64",
+        "--skip-if-cached",
+    ])
+    .unwrap();
+    assert_eq!(read_png(output.to_str().unwrap()).width(), 256);
+
+    std::fs::write(&output, b"not a png").unwrap();
+    assert!(!gst_thumbnailers::is_thumbnail_valid(&output, &uri, mtime));
+}
+
+#[test]
+fn test_thumbnail_batch_parallel() {
+    let dir = tempfile::tempdir().unwrap();
+    let requests = ["1.webm", "2.webm", "3.webm", "not-existing.webm"]
+        .iter()
+        .zip(0..)
+        .map(|(video, id)| gst_thumbnailers::ThumbnailRequest {
+            id,
+            source_uri: gio::File::for_path(format!("tests/{video}"))
+                .uri()
+                .to_string(),
+            output_path: dir.path().join(format!("{video}.png")),
+            kind: gst_thumbnailers::ThumbnailerKind::Video,
+            config_override: None,
+        })
+        .collect::<Vec<_>>();
+
+    let results = gst_thumbnailers::thumbnail_batch_parallel(&requests, &Default::default(), 2);
+    assert_eq!(results.len(), requests.len());
+    // The results are in the order of the requests
+    for (request, result) in requests.iter().zip(&results[..3]) {
+        assert_eq!(result.id, request.id);
+        let meta = result.outcome.as_ref().unwrap();
+        let frame = read_png(request.output_path.to_str().unwrap());
+        assert_eq!((meta.width, meta.height), (frame.width(), frame.height()));
+    }
+    assert!(results[3].outcome.is_err());
+}
+
+#[test]
+fn test_thumbnail_batch_init_error() {
+    let requests = [0, 1].map(|id| gst_thumbnailers::ThumbnailRequest {
+        id,
+        source_uri: gio::File::for_path("tests/1.webm").uri().to_string(),
+        output_path: "tests/test-batch-init-error-output.png".into(),
+        kind: gst_thumbnailers::ThumbnailerKind::Video,
+        config_override: None,
+    });
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        seek_percentages: Some(vec![150]),
+        ..Default::default()
+    };
+
+    // Every request gets the original error
+    for result in gst_thumbnailers::thumbnail_batch(&requests, &config) {
+        let err = result.outcome.unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                gst_thumbnailers::ErrorKind::Other(msg)
+                    if msg == "Seek percentages have to be at most 100, got 150"
+            ),
+            "{err}"
+        );
+        assert_eq!(
+            err.to_string().matches("gst-thumbnailers:").count(),
+            1,
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn test_thumbnail_queue() {
+    let dir = tempfile::tempdir().unwrap();
+    let request = |(video, id): (&str, u64)| gst_thumbnailers::ThumbnailRequest {
+        id,
+        source_uri: gio::File::for_path(format!("tests/{video}"))
+            .uri()
+            .to_string(),
+        output_path: dir.path().join(format!("{video}.png")),
+        kind: gst_thumbnailers::ThumbnailerKind::Video,
+        config_override: None,
+    };
+    let videos = [("1.webm", 1), ("2.webm", 2), ("3.webm", 3)];
+
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let (queue, handle) = gst_thumbnailers::ThumbnailQueue::new(Default::default(), 2, 4, {
+        let results = results.clone();
+        move |result| results.lock().unwrap().push(result)
+    });
+    for video in videos {
+        queue.push(request(video)).unwrap();
+    }
+    drop(queue);
+    handle.join().unwrap();
+
+    let results = results.lock().unwrap();
+    assert!(
+        results.iter().all(|result| result.outcome.is_ok()),
+        "{results:?}"
+    );
+    // Results arrive in the order in which the requests finish
+    let mut ids = results.iter().map(|result| result.id).collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, [1, 2, 3]);
+
+    // A single slot fills up while the worker is busy
+    let (queue, handle) = gst_thumbnailers::ThumbnailQueue::new(Default::default(), 1, 1, |_| {});
+    let pushed = videos.map(|video| queue.push(request(video)));
+    assert!(pushed.iter().any(Result::is_err));
+    drop(queue);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_video_thumbnailer_nul_in_path() {
+    let err = gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1\0.webm",
+        "-o",
+        "tests/test-video-output-nul.png",
+        "-s",
+        "256",
+    ])
+    .unwrap_err();
+    assert!(err.to_string().contains("NUL"), "{err}");
+}
+
+#[test]
+fn test_video_thumbnailer_relative_path() {
+    let output = "tests/test-video-output-relative.png";
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/../tests/1.webm",
+        "-o",
+        output,
+        "-s",
+        "256",
+    ])
+    .unwrap();
+
+    let uri = gio::File::for_path(std::fs::canonicalize("tests/1.webm").unwrap()).uri();
+    let chunks = png_text_chunks(output);
+    assert!(
+        chunks.contains(&("Thumb::URI".into(), uri.to_string())),
+        "{chunks:?}"
+    );
+}
+
+#[test]
+fn test_video_thumbnailer_corrupt_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("corrupt.webm");
+    let output = dir.path().join("corrupt.png");
+
+    // Deterministic pseudo-random data
+    let mut state = 0x2545_f491_u32;
+    let data = (0..512)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect::<Vec<_>>();
+    std::fs::write(&input, data).unwrap();
+
+    let err = gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "256",
+    ])
+    .unwrap_err();
+
+    assert!(
+        err.to_string()
+            .contains("Could not determine type of stream"),
+        "{err}"
+    );
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_video_thumbnailer_very_short() {
+    // 0.5 seconds, so all seek positions are within the first few frames
+    let frame = run_video_thumbnailer("short.webm").unwrap();
+    let var = frame_variance(&frame);
+
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+    assert!(var > 100., "{var:.0} is too low");
+}
+
+#[test]
+fn test_min_variance_threshold() {
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let output = Path::new("tests/test-video-min-variance-output.png");
+
+    // The frames of the test video have a variance of about 2200
+    let config = |threshold| gst_thumbnailers::ThumbnailerConfig {
+        min_variance_threshold: Some(threshold),
+        ..Default::default()
+    };
+
+    gst_thumbnailers::thumbnail_video(&uri, output, &config(1000.), None).unwrap();
+
+    let err = gst_thumbnailers::thumbnail_video(&uri, output, &config(10000.), None).unwrap_err();
+    assert!(
+        matches!(
+            err.kind(),
+            gst_thumbnailers::ErrorKind::BlankFrames { best_variance }
+                if (1000. ..10000.).contains(best_variance)
+        ),
+        "{err}"
+    );
+
+    // The threshold applies to the variance, not to the selector's score
+    struct ZeroSelector;
+
+    impl gst_thumbnailers::FrameSelector for ZeroSelector {
+        fn score(&self, _: &gst_thumbnailers::FrameRef) -> f32 {
+            0.
+        }
+    }
+
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        min_variance_threshold: Some(1000.),
+        frame_selector: Box::new(ZeroSelector),
+        ..Default::default()
+    };
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap();
+}
+
+#[test]
+fn test_tone_map_hdr() {
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        tone_map_hdr: true,
+        ..Default::default()
+    };
+
+    // SDR video has to pass through unchanged
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    match gst_thumbnailers::thumbnail_video_to_bytes(&uri, &config, None) {
+        Ok(data) => {
+            let reference =
+                gst_thumbnailers::thumbnail_video_to_bytes(&uri, &Default::default(), None)
+                    .unwrap();
+            assert_eq!(decode(data).buf_bytes(), decode(reference).buf_bytes());
+        }
+        Err(err) => assert!(
+            matches!(err.kind(), gst_thumbnailers::ErrorKind::MissingPlugin(name) if name == "tonemap"),
+            "{err}"
+        ),
+    }
+
+    // Uniform gray at the HLG reference level of 75%, which would end up at
+    // 191 without converting the transfer function
+    let uri = gio::File::for_path("tests/hdr-hlg.mkv").uri();
+    let data = gst_thumbnailers::thumbnail_video_to_bytes(&uri, &Default::default(), None).unwrap();
+    let mean = mean_component(&decode(data));
+    assert!((100. ..175.).contains(&mean), "{mean}");
+
+    match gst_thumbnailers::thumbnail_video_to_bytes(&uri, &config, None) {
+        Ok(data) => {
+            let mean = mean_component(&decode(data));
+            assert!((40. ..220.).contains(&mean), "{mean}");
+        }
+        Err(err) => assert!(
+            matches!(err.kind(), gst_thumbnailers::ErrorKind::MissingPlugin(name) if name == "tonemap"),
+            "{err}"
+        ),
+    }
+}
+
+#[test]
+fn test_10bit_video_not_clipped() {
+    // Horizontal gradient from 10% to 90% in 10-bit SDR BT.709, so only the
+    // bit depth differs from the other test videos
+    let uri = gio::File::for_path("tests/10bit-gradient.mkv").uri();
+    let data = gst_thumbnailers::thumbnail_video_to_bytes(&uri, &Default::default(), None).unwrap();
+    let components = frame_components(&decode(data));
+
+    let min = *components.iter().min().unwrap();
+    let max = *components.iter().max().unwrap();
+    assert!(min > 0 && max < 255, "{min}..{max}");
+    assert!(max - min > 100, "{min}..{max}");
+}
+
+#[test]
+fn test_pipeline_timeout() {
+    // Prerolling waits for the state change with a timeout
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        pipeline_timeout: Some(std::time::Duration::from_secs(30)),
+        ..Default::default()
+    };
+
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let output = Path::new("tests/test-video-pipeline-timeout-output.png");
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap();
+
+    let uri = gio::File::for_path("tests/audio-cover-png.flac").uri();
+    let output = Path::new("tests/test-audio-pipeline-timeout-output.png");
+    gst_thumbnailers::thumbnail_audio(&uri, output, &config, None).unwrap();
+}
+
+#[test]
+fn test_preroll_retries() {
+    // The source is missing on the first attempt and appears while waiting
+    let input = Path::new("tests/test-preroll-retries-input.webm");
+    let _ = std::fs::remove_file(input);
+    let uri = gio::File::for_path(input).uri();
+    let output = Path::new("tests/test-preroll-retries-output.png");
+
+    let config = gst_thumbnailers::ThumbnailerConfig::default();
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap_err();
+
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        preroll_retries: 10,
+        preroll_retry_delay: std::time::Duration::from_millis(100),
+        ..Default::default()
+    };
+    let copy = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        // Renamed so that the file never appears partially written
+        std::fs::copy("tests/1.webm", "tests/test-preroll-retries-input.tmp").unwrap();
+        std::fs::rename(
+            "tests/test-preroll-retries-input.tmp",
+            "tests/test-preroll-retries-input.webm",
+        )
+        .unwrap();
+    });
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap();
+    copy.join().unwrap();
+
+    std::fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn test_preroll_retries_error_message() {
+    // The source can be opened, but isn't a video on the first attempt. This
+    // fails in the streaming thread, after the state change started.
+    let input = Path::new("tests/test-preroll-retries-error-input.webm");
+    std::fs::write(input, [0; 4096]).unwrap();
+    let uri = gio::File::for_path(input).uri();
+    let output = Path::new("tests/test-preroll-retries-error-output.png");
+
+    let config = gst_thumbnailers::ThumbnailerConfig::default();
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap_err();
+
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        preroll_retries: 10,
+        preroll_retry_delay: std::time::Duration::from_millis(100),
+        ..Default::default()
+    };
+    let copy = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        std::fs::copy("tests/1.webm", "tests/test-preroll-retries-error-input.tmp").unwrap();
+        std::fs::rename(
+            "tests/test-preroll-retries-error-input.tmp",
+            "tests/test-preroll-retries-error-input.webm",
+        )
+        .unwrap();
+    });
+    gst_thumbnailers::thumbnail_video(&uri, output, &config, None).unwrap();
+    copy.join().unwrap();
+
+    read_png(output.to_str().unwrap());
+
+    std::fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn test_custom_frame_selector() {
+    struct CountingSelector(Arc<Mutex<usize>>);
+
+    impl gst_thumbnailers::FrameSelector for CountingSelector {
+        fn score(&self, frame: &gst_thumbnailers::FrameRef) -> f32 {
+            assert_eq!((frame.width, frame.height), (128, 72));
+            *self.0.lock().unwrap() += 1;
+            0.
+        }
+    }
+
+    let calls = Arc::new(Mutex::new(0));
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        frame_selector: Box::new(CountingSelector(calls.clone())),
+        ..Default::default()
+    };
+    gst_thumbnailers::thumbnail_video(
+        &gio::File::for_path("tests/1.webm").uri(),
+        Path::new("tests/test-video-frame-selector-output.png"),
+        &config,
+        None,
+    )
+    .unwrap();
+
+    // Preroll frame and five seek positions
+    assert_eq!(*calls.lock().unwrap(), 6);
+}
+
 #[test]
 fn test_video_thumbnailer_on_audio() {
     let err = run_video_thumbnailer("audio-cover-jpg.mp3").unwrap_err();
-    assert!(
-        err.to_string()
-            .contains("Stream is of type 'audio' instead of 'video'")
-    );
+    assert!(matches!(
+        err.kind(),
+        gst_thumbnailers::ErrorKind::NoVideoStream
+    ));
+}
+
+#[test]
+fn test_audio_thumbnailer_on_video() {
+    let err = gst_thumbnailers::main_audio_thumbnailer([
+        "gst-audio-thumbnailer",
+        "-i",
+        &gio::File::for_path("tests/short.webm").uri(),
+        "-o",
+        "tests/test-audio-output-short.webm.png",
+    ])
+    .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        gst_thumbnailers::ErrorKind::NoAudioStream
+    ));
 }
 
 #[test]
@@ -40,14 +1239,11 @@ fn test_audio_thumbnailer() {
     for (path, var_ref) in [
         ("audio-cover-jpg.mp3", 14500.),
         ("audio-cover-png.flac", 14500.),
+        // The cover is stored as a file icon, which becomes a preview image
+        ("audio-preview-image.flac", 14500.),
     ] {
         let frame = run_audio_thumbnailer(path);
-        let var = gst_thumbnailers::variance(
-            &frame.buf_bytes(),
-            frame.width(),
-            frame.stride(),
-            frame.height(),
-        );
+        let var = frame_variance(&frame);
 
         assert!(
             f32::abs(var - var_ref) < 200.,
@@ -56,33 +1252,822 @@ fn test_audio_thumbnailer() {
     }
 }
 
+#[test]
+fn test_audio_thumbnailer_front_cover() {
+    // Contains the usual cover as an undefined image followed by a plain
+    // gray front cover
+    let frame = run_audio_thumbnailer("audio-multi-cover.flac");
+    let var = frame_variance(&frame);
+
+    assert!(
+        var < 100.,
+        "{var:.0} is not the variance of the front cover"
+    );
+}
+
+#[test]
+fn test_audio_thumbnailer_broken_front_cover() {
+    // The front cover is a truncated PNG, so the undefined image has to be
+    // used instead
+    let frame = run_audio_thumbnailer("audio-broken-cover.flac");
+    let var = frame_variance(&frame);
+
+    assert!(
+        f32::abs(var - 14500.) < 200.,
+        "{var:.0} is not the variance of the undefined image"
+    );
+}
+
+#[test]
+fn test_audio_thumbnailer_cover_art_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let audio = dir.path().join("audio.flac");
+    let cover = dir.path().join("folder.jpg");
+    let output = dir.path().join("thumbnail.png");
+    std::fs::copy("tests/audio-no-cover.flac", &audio).unwrap();
+
+    // Use a cover from another file as folder.jpg
+    gst_thumbnailers::main_audio_thumbnailer([
+        "gst-audio-thumbnailer",
+        "-p",
+        "tests/audio-cover-jpg.mp3",
+        "-o",
+        cover.to_str().unwrap(),
+        "-s",
+        "256",
+    ])
+    .unwrap();
+
+    gst_thumbnailers::main_audio_thumbnailer([
+        "gst-audio-thumbnailer",
+        "-p",
+        audio.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "256",
+        "--cover-art-fallback-dir",
+        ".",
+    ])
+    .unwrap();
+
+    let frame = read_png(output.to_str().unwrap());
+    let var = frame_variance(&frame);
+
+    assert!(var > 10000., "{var:.0} is not the variance of the cover");
+}
+
+#[test]
+fn test_audio_thumbnailer_waveform() {
+    let output = "tests/test-audio-waveform-output.png";
+    gst_thumbnailers::main_audio_thumbnailer([
+        "gst-audio-thumbnailer",
+        "-p",
+        "tests/audio-no-cover.flac",
+        "-o",
+        output,
+        "-s",
+        "256",
+        "--audio-fallback",
+        "waveform",
+    ])
+    .unwrap();
+
+    let frame = read_png(output);
+    assert_eq!((frame.width(), frame.height()), (256, 128));
+    assert!(frame_variance(&frame) > 0.);
+}
+
+#[test]
+fn test_audio_thumbnailer_spectrogram() {
+    let uri = gio::File::for_path("tests/audio-no-cover.flac").uri();
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        size_mode: gst_thumbnailers::SizeMode::Exact {
+            width: 200,
+            height: 100,
+            background: [0, 0, 0],
+        },
+        audio_fallback: gst_thumbnailers::AudioFallback::Spectrogram,
+        spectrogram_dynamic_range_db: 60.,
+        ..Default::default()
+    };
+
+    let frame = decode(gst_thumbnailers::thumbnail_audio_to_bytes(&uri, &config, None).unwrap());
+    assert_eq!((frame.width(), frame.height()), (200, 100));
+    assert!(frame_variance(&frame) > 0.);
+}
+
+#[test]
+fn test_min_cover_resolution() {
+    // Contains the usual 200×200 cover as an undefined image followed by a
+    // gray 16×16 front cover
+    let uri = gio::File::for_path("tests/audio-small-cover.flac").uri();
+    let variance = |min_cover_resolution| {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            min_cover_resolution,
+            ..Default::default()
+        };
+        let frame =
+            decode(gst_thumbnailers::thumbnail_audio_to_bytes(&uri, &config, None).unwrap());
+        frame_variance(&frame)
+    };
+
+    // The front cover is preferred
+    assert!(variance(None) < 100.);
+    // The front cover is too small
+    assert!(f32::abs(variance(Some((64, 64))) - 14500.) < 200.);
+    // Both are too small, so the larger one is used
+    assert!(f32::abs(variance(Some((1000, 1000))) - 14500.) < 200.);
+}
+
+#[test]
+fn test_tag_image_type() {
+    use gst_thumbnailers::TagImageType;
+
+    assert_eq!(TagImageType::try_from(-1).unwrap(), TagImageType::None);
+    assert_eq!(TagImageType::try_from(1).unwrap(), TagImageType::FrontCover);
+    assert_eq!(
+        TagImageType::try_from(18).unwrap(),
+        TagImageType::PublisherStudioLogo
+    );
+    assert!(TagImageType::try_from(19).is_err());
+}
+
+#[test]
+fn test_audio_thumbnailer_no_cover() {
+    let err = gst_thumbnailers::main_audio_thumbnailer([
+        "gst-audio-thumbnailer",
+        "-p",
+        "tests/audio-no-cover.flac",
+        "-o",
+        "tests/test-audio-no-cover-output.png",
+        "-s",
+        "256",
+    ])
+    .unwrap_err();
+
+    assert!(err.to_string().contains("No tag image found"), "{err}");
+}
+
+#[test]
+fn test_cancelled() {
+    let cancellable = gio::Cancellable::new();
+    cancellable.cancel();
+
+    let err = gst_thumbnailers::thumbnail_video(
+        &gio::File::for_path("tests/1.webm").uri(),
+        Path::new("tests/test-cancelled-output.png"),
+        &Default::default(),
+        Some(&cancellable),
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("Cancelled"));
+}
+
+#[test]
+fn test_progress() {
+    use gst_thumbnailers::{ThumbnailerProgress, ThumbnailerStage};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let config = gst_thumbnailers::ThumbnailerConfig {
+        on_progress: Some(Box::new({
+            let events = events.clone();
+            move |progress: ThumbnailerProgress| events.lock().unwrap().push(progress)
+        })),
+        ..Default::default()
+    };
+
+    gst_thumbnailers::thumbnail_video(
+        &gio::File::for_path("tests/1.webm").uri(),
+        Path::new("tests/test-progress-output.png"),
+        &config,
+        None,
+    )
+    .unwrap();
+
+    let events = events.lock().unwrap();
+    let stages = events.iter().map(|x| x.stage).collect::<Vec<_>>();
+    assert_eq!(
+        stages,
+        [
+            ThumbnailerStage::Prerolling,
+            ThumbnailerStage::SeekingFrame(1, 5),
+            ThumbnailerStage::SeekingFrame(2, 5),
+            ThumbnailerStage::SeekingFrame(3, 5),
+            ThumbnailerStage::SeekingFrame(4, 5),
+            ThumbnailerStage::SeekingFrame(5, 5),
+            ThumbnailerStage::Encoding,
+        ]
+    );
+    assert!(events.is_sorted_by(|a, b| a.fraction < b.fraction));
+}
+
+#[test]
+fn test_error_source() {
+    use std::error::Error;
+
+    let err = gst_thumbnailers::Error::from(gio::glib::Error::new(
+        gio::IOErrorEnum::NotFound,
+        "File not found",
+    ));
+
+    // The kind is already part of the message, so the chain continues with
+    // the wrapped error
+    let source = err.source().unwrap();
+    assert!(source.is::<gio::glib::Error>());
+    assert_eq!(source.to_string(), "File not found");
+    assert!(source.source().is_none());
+
+    // Kinds without a wrapped error end the chain
+    let err = gst_thumbnailers::Error::other("No video stream");
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn test_scale_thumbnail_dimensions_edge_cases() {
+    use gst_thumbnailers::geometry::scale_thumbnail_dimensions;
+
+    assert_eq!(scale_thumbnail_dimensions(0., 0., 256), (0, 0));
+    // No upscaling
+    assert_eq!(scale_thumbnail_dimensions(100., 100., 256), (100, 100));
+    // Extreme aspect ratios don't collapse to zero pixels
+    assert_eq!(scale_thumbnail_dimensions(1000., 1., 256), (256, 1));
+    assert_eq!(scale_thumbnail_dimensions(1., 1000., 256), (1, 256));
+    // Exactly the maximum size
+    assert_eq!(scale_thumbnail_dimensions(256., 256., 256), (256, 256));
+}
+
+#[test]
+fn test_scale_thumbnail_dimensions() {
+    use gst_thumbnailers::geometry::*;
+
+    assert_eq!(scale_thumbnail_dimensions(0., 0., 256), (0, 0));
+    assert_eq!(scale_thumbnail_dimensions(0., 1000., 256), (0, 256));
+    assert_eq!(scale_thumbnail_dimensions(512., 512., 256), (256, 256));
+    assert_eq!(scale_thumbnail_dimensions(1920., 1080., 256), (256, 144));
+
+    // Square pixels
+    assert_eq!(
+        scale_thumbnail_dimensions_with_par(1920., 1080., 1, 1, 256),
+        (256, 144)
+    );
+    // Wide pixels
+    assert_eq!(
+        scale_thumbnail_dimensions_with_par(320., 240., 4, 3, 256),
+        (256, 144)
+    );
+    // Narrow pixels
+    assert_eq!(
+        scale_thumbnail_dimensions_with_par(640., 480., 1, 2, 256),
+        (171, 256)
+    );
+    // Equal display width and height
+    assert_eq!(
+        scale_thumbnail_dimensions_with_par(480., 640., 4, 3, 256),
+        (256, 256)
+    );
+    assert_eq!(
+        scale_thumbnail_dimensions_with_par(0., 0., 4, 3, 256),
+        (0, 0)
+    );
+    // Invalid ratio
+    assert_eq!(
+        scale_thumbnail_dimensions_with_par(512., 256., 1, 0, 256),
+        (256, 128)
+    );
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn test_variance_simd() {
+    // Odd width and padding to cover the scalar remainders
+    let (width, height, stride) = (67, 13, 67 * 3 + 3);
+    let xs = (0..stride * height)
+        .map(|i| (i * 7919 % 251) as u8)
+        .collect::<Vec<_>>();
+
+    let scalar = gst_thumbnailers::variance_scalar(&xs, width, stride, height);
+    let simd = gst_thumbnailers::variance_simd(&xs, width, stride, height);
+    // Summation order differs, so allow a few ulps of relative error
+    assert!((scalar - simd).abs() <= scalar * 8. * f32::EPSILON);
+}
+
+#[test]
+fn test_thumbnailer_concurrent() {
+    let thumbnailer = gst_thumbnailers::Thumbnailer::new(Default::default()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    std::thread::scope(|s| {
+        for (i, video) in ["1.webm", "2.webm", "3.webm", "1.webm"]
+            .into_iter()
+            .enumerate()
+        {
+            let thumbnailer = &thumbnailer;
+            let output = dir.path().join(format!("{i}.png"));
+            s.spawn(move || {
+                let uri = gio::File::for_path(format!("tests/{video}")).uri();
+                thumbnailer.thumbnail_video(&uri, &output).unwrap();
+
+                let frame = read_png(output.to_str().unwrap());
+                assert_eq!((frame.width(), frame.height()), (128, 72));
+            });
+        }
+    });
+}
+
+#[test]
+fn test_decoder_threads() {
+    use gst_thumbnailers::DecoderThreads;
+
+    assert!(DecoderThreads::Auto.threads().get() >= 1);
+    assert!(DecoderThreads::Auto.threads().get() <= 4);
+    assert_eq!("auto".parse::<DecoderThreads>(), Ok(DecoderThreads::Auto));
+    assert_eq!("2".parse::<DecoderThreads>().unwrap().threads().get(), 2);
+    assert!("0".parse::<DecoderThreads>().is_err());
+}
+
+#[test]
+fn test_config_from_env() {
+    use gst_thumbnailers::{DecoderThreads, OutputFormat, SizeMode, ThumbnailerConfig};
+
+    let vars = [
+        ("GST_THUMBNAILER_SIZE", "128"),
+        ("GST_THUMBNAILER_FORMAT", "jpeg"),
+        ("GST_THUMBNAILER_NUM_FRAMES", "3"),
+        ("GST_THUMBNAILER_SEEK_PERCENTAGES", "5, 50,95"),
+        ("GST_THUMBNAILER_DECODER_THREADS", "4"),
+        ("GST_THUMBNAILER_TIMEOUT_SECS", "30"),
+    ];
+    for (name, value) in vars {
+        // SAFETY: No other test reads or writes these variables
+        unsafe { std::env::set_var(name, value) };
+    }
+
+    let config = ThumbnailerConfig::from_env();
+
+    for (name, _) in vars {
+        // SAFETY: See above
+        unsafe { std::env::remove_var(name) };
+    }
+
+    let config = config.unwrap();
+    assert_eq!(config.size_mode, SizeMode::Fit { max: 128 });
+    assert_eq!(config.output_format, OutputFormat::Jpeg);
+    assert_eq!(config.num_seek_positions.get(), 3);
+    assert_eq!(config.seek_percentages, Some(vec![5, 50, 95]));
+    assert_eq!(
+        config.decoder_threads,
+        DecoderThreads::Fixed(std::num::NonZeroU32::new(4).unwrap())
+    );
+    assert_eq!(
+        config.pipeline_timeout,
+        Some(std::time::Duration::from_secs(30))
+    );
+    // Absent variables keep their defaults
+    assert_eq!(config.jpeg_quality, 85);
+    assert!(!config.allow_hardware_decoders);
+
+    for value in ["10,150", "10,,20", "ten", ""] {
+        // SAFETY: See above
+        unsafe { std::env::set_var("GST_THUMBNAILER_SEEK_PERCENTAGES", value) };
+        let result = ThumbnailerConfig::from_env();
+        // SAFETY: See above
+        unsafe { std::env::remove_var("GST_THUMBNAILER_SEEK_PERCENTAGES") };
+        assert!(result.is_err(), "{value:?}");
+    }
+}
+
+#[test]
+fn test_intermediate_scale_factor() {
+    let uri = gio::File::for_path("tests/audio-cover-jpg.mp3").uri();
+    let thumbnail = |intermediate_scale_factor| {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            intermediate_scale_factor,
+            ..Default::default()
+        };
+        gst_thumbnailers::thumbnail_audio_to_bytes(&uri, &config, None)
+    };
+
+    let expected = decode(thumbnail(2.).unwrap());
+    for factor in [1., 3.5] {
+        let frame = decode(thumbnail(factor).unwrap());
+        assert_eq!(
+            (frame.width(), frame.height()),
+            (expected.width(), expected.height())
+        );
+    }
+
+    assert!(thumbnail(0.5).is_err());
+    assert!(thumbnail(f32::NAN).is_err());
+}
+
+#[test]
+fn test_scale_presets() {
+    let uri = gio::File::for_path("tests/audio-cover-jpg.mp3").uri();
+    let thumbnail = |preset: gst_thumbnailers::ScalePreset| {
+        let config = gst_thumbnailers::ThumbnailerConfig {
+            size_mode: gst_thumbnailers::SizeMode::Fit { max: 64 },
+            scale_filters: preset.into(),
+            ..Default::default()
+        };
+        decode(gst_thumbnailers::thumbnail_audio_to_bytes(&uri, &config, None).unwrap())
+    };
+
+    let fast = thumbnail(gst_thumbnailers::ScalePreset::Fast);
+    let balanced = thumbnail(gst_thumbnailers::ScalePreset::Balanced);
+    let high_quality = thumbnail(gst_thumbnailers::ScalePreset::HighQuality);
+    assert_ne!(fast.buf_bytes(), high_quality.buf_bytes());
+
+    // The filters only differ in details
+    let reference = frame_variance(&balanced);
+    for frame in [&fast, &high_quality] {
+        assert_eq!(
+            (frame.width(), frame.height()),
+            (balanced.width(), balanced.height())
+        );
+        let difference = (frame_variance(frame) - reference).abs() / reference;
+        assert!(difference < 0.25, "Variance differs by {difference}");
+    }
+}
+
+#[cfg(feature = "dbus")]
+#[test]
+fn test_dbus_service() {
+    // Needs a session bus, like with `dbus-run-session cargo test`
+    let Ok(client) = zbus::blocking::Connection::session() else {
+        eprintln!("Skipping D-Bus test without a session bus");
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let _service = gst_thumbnailers::GstThumbnailerService {
+        cache_dir: dir.path().to_path_buf(),
+        workers: 1,
+        ..Default::default()
+    }
+    .connect()
+    .unwrap();
+
+    let proxy = zbus::blocking::Proxy::new(
+        &client,
+        gst_thumbnailers::BUS_NAME,
+        gst_thumbnailers::OBJECT_PATH,
+        "org.freedesktop.thumbnails.SpecializedThumbnailer1",
+    )
+    .unwrap();
+
+    let mut ready = proxy.receive_signal("Ready").unwrap();
+    let mut errors = proxy.receive_signal("Error").unwrap();
+    let mut finished = proxy.receive_signal("Finished").unwrap();
+    let uri = gio::File::for_path("tests/1.webm").uri();
+    let handle: u32 = proxy
+        .call("Queue", &(uri.as_str(), "video/webm", "normal", false))
+        .unwrap();
+    let (ready_handle, ready_uri): (u32, String) =
+        ready.next().unwrap().body().deserialize().unwrap();
+    assert_eq!((ready_handle, ready_uri.as_str()), (handle, uri.as_str()));
+    let finished_handle: u32 = finished.next().unwrap().body().deserialize().unwrap();
+    assert_eq!(finished_handle, handle);
+
+    // Only video and audio files are supported
+    let handle: u32 = proxy
+        .call("Queue", &(uri.as_str(), "text/plain", "normal", false))
+        .unwrap();
+    let (error_handle, _, code, _): (u32, String, i32, String) =
+        errors.next().unwrap().body().deserialize().unwrap();
+    assert_eq!((error_handle, code), (handle, 0));
+
+    let hash = gio::glib::compute_checksum_for_string(gio::glib::ChecksumType::Md5, &uri).unwrap();
+    let output = dir.path().join("normal").join(format!("{hash}.png"));
+    let frame = read_png(output.to_str().unwrap());
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_config_toml_round_trip() {
+    use gst_thumbnailers::{
+        OutputFormat, PngCompression, SeekPositions, SizeMode, ThumbnailerConfig,
+        VideoStreamSelector,
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+
+    let config = ThumbnailerConfig {
+        size_mode: SizeMode::Exact {
+            width: 320,
+            height: 180,
+            background: [10, 20, 30],
+        },
+        pipeline_timeout: Some(std::time::Duration::from_millis(1500)),
+        output_format: OutputFormat::WebP,
+        png_compression: PngCompression::Best,
+        video_stream_selector: VideoStreamSelector::StreamIndex(2),
+        seek_positions: SeekPositions::Chapter(1),
+        min_variance_threshold: Some(12.5),
+        cover_art_fallback_dirs: vec![".".into(), "Artwork".into()],
+        ..Default::default()
+    };
+    config.to_toml_file(&path).unwrap();
+
+    let loaded = ThumbnailerConfig::from_toml_file(&path).unwrap();
+    assert_eq!(loaded.size_mode, config.size_mode);
+    assert_eq!(loaded.pipeline_timeout, config.pipeline_timeout);
+    assert_eq!(loaded.output_format, config.output_format);
+    assert_eq!(loaded.png_compression, config.png_compression);
+    assert_eq!(loaded.video_stream_selector, config.video_stream_selector);
+    assert_eq!(loaded.seek_positions, config.seek_positions);
+    assert_eq!(loaded.min_variance_threshold, config.min_variance_threshold);
+    assert_eq!(
+        loaded.cover_art_fallback_dirs,
+        config.cover_art_fallback_dirs
+    );
+
+    // Everything else has to survive as well
+    let reloaded_path = dir.path().join("reloaded.toml");
+    loaded.to_toml_file(&reloaded_path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        std::fs::read_to_string(&reloaded_path).unwrap()
+    );
+}
+
+#[test]
+fn test_luminance_variance() {
+    // 4×4 RGB frames without padding
+    let (width, stride, height) = (4, 12, 4);
+
+    // Dark frame with strong blue grain
+    let blue_grain = [[0, 0, 0], [0, 0, 255]].repeat(8).concat();
+    // Brighter frame with gray texture
+    let gray_texture = [[100, 100, 100], [140, 140, 140]].repeat(8).concat();
+
+    let rgb = |data: &[u8]| {
+        gst_thumbnailers::variance(&gst_thumbnailers::FrameRef {
+            data,
+            width,
+            height,
+            stride,
+        })
+    };
+    let luminance = |xs: &[u8]| gst_thumbnailers::luminance_variance(xs, width, stride, height);
+
+    assert!(rgb(&blue_grain) > rgb(&gray_texture));
+    assert!(luminance(&blue_grain) < luminance(&gray_texture));
+
+    assert!(f32::abs(luminance(&gray_texture) - 400.) < 0.1);
+}
+
+#[test]
+fn test_is_likely_black_frame() {
+    // 4×4 RGB frames without padding
+    let (width, stride, height) = (4, 12, 4);
+    let is_black =
+        |xs: &[u8]| gst_thumbnailers::is_likely_black_frame(xs, width, stride, height, 10);
+
+    assert!(is_black(&[0; 4 * 4 * 3]));
+    // Near-black with a little noise
+    assert!(is_black(&[[3, 5, 2], [9, 8, 12]].repeat(8).concat()));
+    // Dark gray
+    assert!(!is_black(&[20; 4 * 4 * 3]));
+    // Mostly black with a bright spot
+    let mut spot = vec![0; 4 * 4 * 3];
+    spot[0..9].fill(255);
+    assert!(!is_black(&spot));
+}
+
+#[test]
+fn test_edge_energy() {
+    // 8×8 RGB frames without padding
+    let (width, stride, height) = (8, 24, 8);
+
+    // Hard edge between black and white
+    let sharp = (0..8 * 8)
+        .flat_map(|i| [if i % 8 < 4 { 0 } else { 255 }; 3])
+        .collect::<Vec<u8>>();
+    // Same brightness range smeared over the whole width
+    let blurred = (0..8 * 8)
+        .flat_map(|i| [(i % 8 * 255 / 7) as u8; 3])
+        .collect::<Vec<u8>>();
+    let uniform = vec![128; 8 * 8 * 3];
+
+    let edge_energy = |xs: &[u8]| gst_thumbnailers::edge_energy(xs, width, stride, height);
+
+    assert!(edge_energy(&sharp) > edge_energy(&blurred));
+    assert_eq!(edge_energy(&uniform), 0.);
+
+    // The last row isn't padded to the stride
+    let padded = sharp
+        .chunks_exact(24)
+        .flat_map(|line| [line, &[0; 8]].concat())
+        .collect::<Vec<u8>>();
+    let unpadded_last_row = &padded[..padded.len() - 8];
+    assert_eq!(
+        gst_thumbnailers::edge_energy(unpadded_last_row, width, 32, height),
+        edge_energy(&sharp)
+    );
+
+    // The score doesn't grow with the number of pixels. The edge covers a
+    // smaller share of the larger frame.
+    let sharp_16 = (0..16 * 16)
+        .flat_map(|i| [if i % 16 < 8 { 0 } else { 255 }; 3])
+        .collect::<Vec<u8>>();
+    let energy_16 = gst_thumbnailers::edge_energy(&sharp_16, 16, 48, 16);
+    assert!(energy_16 < edge_energy(&sharp), "{energy_16}");
+    assert!(energy_16 > edge_energy(&sharp) / 4., "{energy_16}");
+
+    // Too small for the Sobel operator
+    assert_eq!(
+        gst_thumbnailers::edge_energy(&[255; 2 * 2 * 3], 2, 6, 2),
+        0.
+    );
+}
+
+#[test]
+fn test_regional_variance() {
+    // 8×8 RGB frame where only the top left quadrant has texture
+    let data = (0..8 * 8)
+        .flat_map(|i| {
+            let (x, y) = (i % 8, i / 8);
+            [if x < 4 && y < 4 && (x + y) % 2 == 0 {
+                255
+            } else {
+                0
+            }; 3]
+        })
+        .collect::<Vec<u8>>();
+    let frame = gst_thumbnailers::FrameRef {
+        data: &data,
+        width: 8,
+        height: 8,
+        stride: 24,
+    };
+
+    let cells = gst_thumbnailers::regional_variance(&frame, 2, 2);
+    assert_eq!(cells.len(), 4);
+    assert!(f32::abs(cells[0] - 127.5 * 127.5) < 0.1);
+    assert_eq!(&cells[1..], [0.; 3]);
+
+    // The textured region stands out more than in the whole frame
+    let score = gst_thumbnailers::FrameQualityMetric::Regional
+        .selector()
+        .score(&frame);
+    assert!(score > gst_thumbnailers::variance(&frame));
+
+    assert!(gst_thumbnailers::regional_variance(&frame, 0, 2).is_empty());
+    // More cells than pixels
+    assert_eq!(gst_thumbnailers::regional_variance(&frame, 16, 1).len(), 16);
+}
+
+#[test]
+fn test_expand_template() {
+    use gst_thumbnailers::expand_template;
+
+    let uri = "file:///videos/holiday/beach.webm";
+    let expand = |template| expand_template(template, uri, (256, 144));
+
+    assert_eq!(expand("%b.png").unwrap(), Path::new("beach.png"));
+    assert_eq!(
+        expand("%d/.thumbs/%b.%e.png").unwrap(),
+        Path::new("/videos/holiday/.thumbs/beach.webm.png")
+    );
+    assert_eq!(
+        expand("/tmp/%b-%Wx%H.jpg").unwrap(),
+        Path::new("/tmp/beach-256x144.jpg")
+    );
+    assert_eq!(expand("100%%.png").unwrap(), Path::new("100%.png"));
+    assert_eq!(expand("thumbnail.png").unwrap(), Path::new("thumbnail.png"));
+
+    // Unknown placeholders are kept
+    assert_eq!(expand("%x-%b.png").unwrap(), Path::new("%x-beach.png"));
+    assert_eq!(expand("thumbnail%").unwrap(), Path::new("thumbnail%"));
+
+    // Without extension
+    assert_eq!(
+        expand_template("%b[%e]", "file:///videos/clip", (1, 1)).unwrap(),
+        Path::new("clip[]")
+    );
+    // Remote files have no local directory
+    assert!(expand_template("%d/%b", "https://example.org/clip.webm", (1, 1)).is_err());
+    assert_eq!(
+        expand_template("%b", "https://example.org/clip.webm", (1, 1)).unwrap(),
+        Path::new("clip")
+    );
+}
+
+#[test]
+fn test_video_thumbnailer_output_template() {
+    let dir = tempfile::tempdir().unwrap();
+    let template = format!("{}/%b-%Wx%H.png", dir.path().display());
+
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        &template,
+        "-s",
+        "64",
+    ])
+    .unwrap();
+
+    let frame = read_png(dir.path().join("1-64x36.png").to_str().unwrap());
+    assert_eq!((frame.width(), frame.height()), (64, 36));
+}
+
+#[test]
+fn test_video_thumbnailer_output_percent() {
+    // Paths without a known placeholder are used literally
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("50%-%x.png");
+
+    gst_thumbnailers::main_video_thumbnailer([
+        "gst-video-thumbnailer",
+        "-p",
+        "tests/1.webm",
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "64",
+    ])
+    .unwrap();
+
+    let frame = read_png(output.to_str().unwrap());
+    assert_eq!((frame.width(), frame.height()), (64, 36));
+}
+
+#[test]
+fn test_audio_thumbnailer_alpha() {
+    let frame = run_audio_thumbnailer("audio-cover-alpha.flac");
+
+    assert_eq!(frame.memory_format(), gly::MemoryFormat::R8g8b8a8);
+    // The cover is a circle on a transparent background
+    assert_eq!(frame.buf_bytes()[3], 0);
+}
+
 fn run_video_thumbnailer(video: &str) -> gst_thumbnailers::Result<gly::Frame> {
+    let output = format!("tests/test-video-output-{video}.png");
+
     gst_thumbnailers::main_video_thumbnailer([
         "gst-video-thumbnailer",
         "-i",
         &gio::File::for_path(format!("tests/{video}")).uri(),
         "-o",
-        "tests/test-video-output.png",
+        &output,
         "-s",
         "256",
     ])?;
 
-    Ok(read_png("tests/test-video-output.png"))
+    Ok(read_png(&output))
 }
 
 fn run_audio_thumbnailer(audio: &str) -> gly::Frame {
+    let output = format!("tests/test-audio-output-{audio}.png");
+
     gst_thumbnailers::main_audio_thumbnailer([
         "gst-audio-thumbnailer",
         "-i",
         &gio::File::for_path(format!("tests/{audio}")).uri(),
         "-o",
-        "tests/test-audio-output.png",
+        &output,
         "-s",
         "256",
     ])
     .unwrap();
 
-    read_png("tests/test-audio-output.png")
+    read_png(&output)
+}
+
+fn decode(data: Vec<u8>) -> gly::Frame {
+    let loader = gly::Loader::for_bytes(&gly::glib::Bytes::from_owned(data));
+    let image = loader.load().unwrap();
+    image.next_frame().unwrap()
+}
+
+fn frame_variance(frame: &gly::Frame) -> f32 {
+    gst_thumbnailers::variance(&gst_thumbnailers::FrameRef {
+        data: &frame.buf_bytes(),
+        width: frame.width(),
+        height: frame.height(),
+        stride: frame.stride(),
+    })
+}
+
+/// RGB components of all pixels, without the padding at the end of rows
+fn frame_components(frame: &gly::Frame) -> Vec<u8> {
+    let row = frame.width() as usize * 3;
+    frame
+        .buf_bytes()
+        .chunks(frame.stride() as usize)
+        .flat_map(|line| line[..row].to_vec())
+        .collect()
+}
+
+fn mean_component(frame: &gly::Frame) -> f32 {
+    let components = frame_components(frame);
+    components.iter().map(|&c| c as f32).sum::<f32>() / components.len() as f32
 }
 
 fn read_png(path: &str) -> gly::Frame {
@@ -90,3 +2075,37 @@ fn read_png(path: &str) -> gly::Frame {
     let image = loader.load().unwrap();
     image.next_frame().unwrap()
 }
+
+/// Keys and values of the tEXt and uncompressed iTXt chunks of a PNG file
+fn png_text_chunks(path: &str) -> Vec<(String, String)> {
+    let data = std::fs::read(path).unwrap();
+    let mut chunks = Vec::new();
+
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let content = &data[pos + 8..pos + 8 + len];
+        pos += 12 + len;
+
+        let mut fields = content.splitn(2, |&b| b == 0);
+        let (Some(key), Some(rest)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let value = match chunk_type {
+            b"tEXt" => rest,
+            // Compression flag and method, language tag, translated keyword
+            b"iTXt" if rest.first() == Some(&0) => {
+                rest[2..].splitn(3, |&b| b == 0).nth(2).unwrap_or_default()
+            }
+            _ => continue,
+        };
+
+        chunks.push((
+            String::from_utf8_lossy(key).into_owned(),
+            String::from_utf8_lossy(value).into_owned(),
+        ));
+    }
+
+    chunks
+}